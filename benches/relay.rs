@@ -0,0 +1,65 @@
+//! End-to-end relay throughput: one sender and one recipient over local
+//! WebSockets against the real binary, pushing 1 MB chunks like the browser
+//! pages do. Run with `cargo bench`; `FILET_BENCH_MB` sets the payload size.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::*;
+use futures_util::SinkExt;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+const CHUNK: usize = 1024 * 1024;
+const DEFAULT_PAYLOAD_MB: usize = 256;
+
+#[tokio::main]
+async fn main() {
+    let chunks: usize = std::env::var("FILET_BENCH_MB")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_PAYLOAD_MB);
+
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, _) = started(&server, (chunks * CHUNK) as u64).await;
+
+    // Send timestamps, consumed in order by the recipient for per-chunk latency
+    let (sent_tx, mut sent_rx) = mpsc::unbounded_channel::<Instant>();
+    let started = Instant::now();
+    let sending = tokio::spawn(async move {
+        for i in 0..chunks {
+            sender.send(frame(CHUNK, i as u8)).await.unwrap();
+            let _ = sent_tx.send(Instant::now());
+        }
+        send_json(&mut sender, serde_json::json!({ "type": "done" })).await;
+        assert_eq!(next_json(&mut sender).await["type"], "delivered");
+    });
+
+    let mut latencies = Vec::with_capacity(chunks);
+    expect_phase(&mut recipient, "active").await;
+    for _ in 0..chunks {
+        let data = next_binary(&mut recipient).await;
+        let received = Instant::now();
+        assert_eq!(data.len(), CHUNK + FRAME_OVERHEAD);
+        let sent = sent_rx.recv().await.expect("send timestamp");
+        latencies.push(received.saturating_duration_since(sent));
+    }
+    assert_eq!(next_json(&mut recipient).await["type"], "done");
+    let elapsed = started.elapsed();
+    sending.await.unwrap();
+
+    latencies.sort();
+    let pct = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    let ms = |d: Duration| d.as_secs_f64() * 1e3;
+    println!(
+        "relay: {chunks} MB in {:.2}s = {:.1} MB/s",
+        elapsed.as_secs_f64(),
+        chunks as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "chunk latency: p50 {:.2}ms  p99 {:.2}ms  max {:.2}ms",
+        ms(pct(50)),
+        ms(pct(99)),
+        ms(pct(100))
+    );
+}
@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Relay-wide cap on bytes sent to recipients, shared by every
+/// `handle_receiver` loop.
+pub struct EgressLimiter {
+    bytes_per_sec: f64,
+    /// Unused allowance carried over from idle periods, at most one second's worth.
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl EgressLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec as f64;
+        Self {
+            bytes_per_sec: rate,
+            burst: rate,
+            bucket: Mutex::new(Bucket {
+                tokens: rate,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Wait until `bytes` may go out. The lock is held while sleeping off a
+    /// deficit, and tokio's mutex queues waiters in FIFO order. Concurrent
+    /// recipients therefore take turns chunk by chunk, so the fastest one
+    /// cannot use up the whole budget.
+    pub async fn acquire(&self, bytes: usize) {
+        let mut bucket = self.bucket.lock().await;
+        let now = Instant::now();
+        let earned = now.duration_since(bucket.refilled_at).as_secs_f64() * self.bytes_per_sec;
+        bucket.tokens = (bucket.tokens + earned).min(self.burst);
+        bucket.refilled_at = now;
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens < 0.0 {
+            let deficit = -bucket.tokens / self.bytes_per_sec;
+            tokio::time::sleep(Duration::from_secs_f64(deficit)).await;
+        }
+    }
+}
@@ -1,37 +1,161 @@
+mod egress;
+mod metrics;
 mod routes;
 mod state;
 mod static_assets;
 mod ws;
 
 use axum::Router;
+use axum::serve::ListenerExt;
 
-use tokio::net::TcpListener;
-use tracing::info;
+use std::io::IsTerminal;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpSocket};
+use tracing::{info, warn};
 
-use crate::state::AppState;
+use crate::state::{AppState, ServerConfig, TransferState};
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+fn bind_listener(port: &str, backlog: u32) -> std::io::Result<TcpListener> {
+    let port: u16 = port
+        .parse()
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid port"))?;
+    let socket = TcpSocket::new_v4()?;
+    socket.set_reuseaddr(true)?;
+    socket.bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)))?;
+    socket.listen(backlog)
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("shutdown requested, no longer accepting connections");
+}
+
+/// Wait for in-flight transfers to finish, up to `timeout`. Returns how many
+/// are still active (and will be cut off when the runtime exits).
+async fn drain_transfers(state: &AppState, timeout: Duration) -> usize {
+    // The listener is closed, so only Active transfers can still complete;
+    // waiting and reconnecting ones have no way for a recipient to arrive.
+    let active = || {
+        state
+            .transfers
+            .iter()
+            .filter(|e| matches!(e.value(), TransferState::Active))
+            .count()
+    };
+    let drained = async {
+        while active() > 0 {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    };
+    let _ = tokio::time::timeout(timeout, drained).await;
+    active()
+}
 
 #[tokio::main]
 async fn main() {
+    // Logs go to stderr so stdout only carries the FILET_JSON_STARTUP line.
+    // Plain logs when piped (journald, docker logs, files) or when NO_COLOR is set.
+    let ansi = std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        && std::io::stderr().is_terminal();
     tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_ansi(ansi)
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "filetransfer=info".into()),
         )
         .init();
 
-    let state = AppState::new();
+    let server = ServerConfig::from_env();
+    let state = AppState::from_env();
+    state.spawn_probe_pruner();
+    if state.ids.is_seeded() {
+        warn!("FILET_TOKEN_SEED is set: transfer ids are predictable, do not use in production");
+    }
 
     let app = Router::new()
         .route("/", axum::routing::get(routes::sender_page))
         .route("/d/{id}", axum::routing::get(routes::receiver_page))
         .route("/api/transfer/{id}", axum::routing::get(routes::transfer_info))
+        .route("/api/meta/{id}", axum::routing::get(routes::signed_meta))
+        .route("/livez", axum::routing::get(routes::livez))
+        .route("/readyz", axum::routing::get(routes::readyz))
+        .route("/metrics", axum::routing::get(routes::metrics))
         .route("/ws/send", axum::routing::get(routes::ws_send))
         .route("/ws/recv/{id}", axum::routing::get(routes::ws_recv))
-        .with_state(state);
+        .with_state(state.clone());
+
+    let listener = match bind_listener(&server.port, server.listen_backlog) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("error: cannot bind to port {}: {e}", server.port);
+            std::process::exit(1);
+        }
+    };
+    let bound = listener.local_addr().expect("listener has a local address");
+    if server.json_startup {
+        println!(
+            "{}",
+            serde_json::json!({
+                "mode": "web",
+                "address": bound.to_string(),
+                "port": bound.port(),
+            })
+        );
+    }
+    info!("filet listening on http://localhost:{}", bound.port());
+    // Small control frames (start/paused/resume) shouldn't wait on Nagle
+    let nodelay = server.tcp_nodelay;
+    let listener = listener.tap_io(move |tcp| {
+        if let Err(e) = tcp.set_nodelay(nodelay) {
+            warn!("failed to set TCP_NODELAY: {e}");
+        }
+    });
+    if let Err(e) = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    {
+        eprintln!("error: server failed: {e}");
+        std::process::exit(1);
+    }
 
-    let port = std::env::var("PORT").unwrap_or_else(|_| "4010".to_string());
-    let addr = format!("0.0.0.0:{port}");
-    let listener = TcpListener::bind(&addr).await.unwrap();
-    info!("filet listening on http://localhost:{port}");
-    axum::serve(listener, app).await.unwrap();
+    // Upgraded WebSockets are detached from axum's connection tracking, so
+    // serve returns as soon as plain HTTP requests are done. Drain relays here.
+    let shutdown_timeout = server.shutdown_timeout;
+    let interrupted = drain_transfers(&state, shutdown_timeout).await;
+    if interrupted > 0 {
+        warn!(interrupted, ?shutdown_timeout, "shutting down with transfers still in progress");
+    } else {
+        info!("all transfers drained, exiting");
+    }
+    let phase_count = |phase| state.transfers.iter().filter(|e| e.value().phase() == phase).count();
+    let (waiting, paused) = (phase_count("waiting"), phase_count("paused"));
+    if waiting + paused > 0 {
+        info!(waiting, paused, "discarding transfers with no recipient connected");
+    }
 }
@@ -0,0 +1,137 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds (seconds) of the reconnect gap histogram buckets.
+const GAP_BUCKETS: [f64; 8] = [0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0];
+
+/// Process-wide counters, rendered in Prometheus text format at `/metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    reconnects_succeeded: AtomicU64,
+    reconnects_timed_out: AtomicU64,
+    reconnects_abandoned: AtomicU64,
+    gap_buckets: [AtomicU64; GAP_BUCKETS.len()],
+    gap_count: AtomicU64,
+    gap_sum_micros: AtomicU64,
+    dropped_frames: AtomicU64,
+    dropped_bytes: AtomicU64,
+    stalls: AtomicU64,
+    stalled_micros: AtomicU64,
+}
+
+pub enum ReconnectOutcome {
+    Succeeded(Duration),
+    TimedOut,
+    /// The sender left (or the slot was dropped) before the recipient returned.
+    Abandoned,
+}
+
+impl Metrics {
+    pub fn record_reconnect(&self, outcome: ReconnectOutcome) {
+        match outcome {
+            ReconnectOutcome::Succeeded(gap) => {
+                self.reconnects_succeeded.fetch_add(1, Ordering::Relaxed);
+                let secs = gap.as_secs_f64();
+                for (bound, bucket) in GAP_BUCKETS.iter().zip(&self.gap_buckets) {
+                    if secs <= *bound {
+                        bucket.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                self.gap_count.fetch_add(1, Ordering::Relaxed);
+                self.gap_sum_micros
+                    .fetch_add(gap.as_micros() as u64, Ordering::Relaxed);
+            }
+            ReconnectOutcome::TimedOut => {
+                self.reconnects_timed_out.fetch_add(1, Ordering::Relaxed);
+            }
+            ReconnectOutcome::Abandoned => {
+                self.reconnects_abandoned.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Frames that were queued for a recipient which went away before sending them.
+    pub fn record_dropped(&self, frames: u64, bytes: u64) {
+        self.dropped_frames.fetch_add(frames, Ordering::Relaxed);
+        self.dropped_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// The relay channel stayed full past the backpressure warning threshold.
+    pub fn record_stall(&self) {
+        self.stalls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How long a stalled relay waited before the recipient drained the channel.
+    pub fn record_stall_time(&self, waited: Duration) {
+        self.stalled_micros
+            .fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let load = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        let mut out = String::new();
+
+        out.push_str("# HELP filet_reconnects_total Recipient reconnect attempts by outcome.\n");
+        out.push_str("# TYPE filet_reconnects_total counter\n");
+        for (outcome, counter) in [
+            ("succeeded", &self.reconnects_succeeded),
+            ("timed_out", &self.reconnects_timed_out),
+            ("abandoned", &self.reconnects_abandoned),
+        ] {
+            let _ = writeln!(
+                out,
+                "filet_reconnects_total{{outcome=\"{outcome}\"}} {}",
+                load(counter)
+            );
+        }
+
+        out.push_str(
+            "# HELP filet_reconnect_gap_seconds Time from recipient drop to successful reconnect.\n",
+        );
+        out.push_str("# TYPE filet_reconnect_gap_seconds histogram\n");
+        for (bound, bucket) in GAP_BUCKETS.iter().zip(&self.gap_buckets) {
+            let _ = writeln!(
+                out,
+                "filet_reconnect_gap_seconds_bucket{{le=\"{bound}\"}} {}",
+                load(bucket)
+            );
+        }
+        let count = load(&self.gap_count);
+        let _ = writeln!(out, "filet_reconnect_gap_seconds_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(
+            out,
+            "filet_reconnect_gap_seconds_sum {}",
+            load(&self.gap_sum_micros) as f64 / 1e6
+        );
+        let _ = writeln!(out, "filet_reconnect_gap_seconds_count {count}");
+
+        out.push_str(
+            "# HELP filet_relay_dropped_frames_total Queued frames discarded when a recipient dropped.\n",
+        );
+        out.push_str("# TYPE filet_relay_dropped_frames_total counter\n");
+        let _ = writeln!(out, "filet_relay_dropped_frames_total {}", load(&self.dropped_frames));
+        out.push_str(
+            "# HELP filet_relay_dropped_bytes_total Bytes in frames discarded when a recipient dropped.\n",
+        );
+        out.push_str("# TYPE filet_relay_dropped_bytes_total counter\n");
+        let _ = writeln!(out, "filet_relay_dropped_bytes_total {}", load(&self.dropped_bytes));
+
+        out.push_str(
+            "# HELP filet_relay_stalls_total Times the relay channel stayed full past the warning threshold.\n",
+        );
+        out.push_str("# TYPE filet_relay_stalls_total counter\n");
+        let _ = writeln!(out, "filet_relay_stalls_total {}", load(&self.stalls));
+        out.push_str(
+            "# HELP filet_relay_stalled_seconds_total Time stalled relays spent waiting for the recipient.\n",
+        );
+        out.push_str("# TYPE filet_relay_stalled_seconds_total counter\n");
+        let _ = writeln!(
+            out,
+            "filet_relay_stalled_seconds_total {}",
+            load(&self.stalled_micros) as f64 / 1e6
+        );
+
+        out
+    }
+}
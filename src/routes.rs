@@ -1,75 +1,298 @@
 use axum::{
-    extract::{Path, Query, State, WebSocketUpgrade},
-    http::{header, StatusCode},
-    response::{Html, IntoResponse, Json},
+    extract::{ConnectInfo, Path, Query, State, WebSocketUpgrade},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Json, Response},
 };
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use tracing::warn;
 
 use crate::state::{AppState, TransferState};
 use crate::static_assets::{RECEIVER_HTML, SENDER_HTML};
 use crate::ws;
 
+/// Transfer pages and metadata describe a one-time, key-bearing link, so keep
+/// them out of browser and proxy caches.
+const NO_STORE: &str = "private, no-store";
+
+fn no_store(mut response: Response) -> Response {
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static(NO_STORE),
+    );
+    response
+}
+
 pub async fn sender_page() -> Html<&'static str> {
     Html(SENDER_HTML)
 }
 
-pub async fn receiver_page(Path(id): Path<String>, State(state): State<AppState>) -> impl IntoResponse {
-    // Check transfer exists (accept both WaitingForRecipient and Reconnecting)
-    let exists = state.transfers.get(&id).map_or(false, |entry| {
-        matches!(
-            entry.value(),
-            TransferState::WaitingForRecipient { .. } | TransferState::Reconnecting { .. }
-        )
-    });
-
-    if !exists {
-        return (
-            StatusCode::NOT_FOUND,
-            [(header::CONTENT_TYPE, "text/html")],
-            "<!DOCTYPE html><html><body style='background:#0a0a0a;color:#e0e0e0;font-family:monospace;display:flex;align-items:center;justify-content:center;height:100vh'><p>transfer not found or expired</p></body></html>".to_string(),
-        );
+fn status_page(
+    status: StatusCode,
+    title: &str,
+    detail: &str,
+) -> (StatusCode, [(header::HeaderName, &'static str); 2], String) {
+    let html = format!(
+        "<!DOCTYPE html><html><head><meta charset='UTF-8'><meta name='viewport' content='width=device-width, initial-scale=1.0'><title>filet - {title}</title></head>\
+         <body style='background:#0a0a0a;color:#e0e0e0;font-family:monospace;display:flex;flex-direction:column;align-items:center;justify-content:center;height:100vh;margin:0'>\
+         <h1 style='color:#fff;font-size:2rem;margin:0 0 0.5rem'>filet</h1><p>{title}</p><p style='color:#666;font-size:0.85rem'>{detail}</p></body></html>"
+    );
+    (
+        status,
+        [(header::CONTENT_TYPE, "text/html"), (header::CACHE_CONTROL, NO_STORE)],
+        html,
+    )
+}
+
+/// Server settings the receiver page needs up front, as a JS object literal.
+/// `<` is escaped so nothing in a value can close the surrounding `<script>`.
+fn page_config(state: &AppState, id: &str) -> String {
+    serde_json::json!({
+        "transfer_id": id,
+        "metadata_api": state.metadata_api,
+        "reconnect_timeout": state.reconnect_timeout.as_secs(),
+        "max_restarts": ws::MAX_RESTARTS,
+    })
+    .to_string()
+    .replace('<', "\\u003c")
+}
+
+/// The address limits and the tarpit are keyed on. With
+/// `FILET_TRUST_FORWARDED_FOR`, that is the last `X-Forwarded-For` hop, which is
+/// the one our own reverse proxy appended. Anything earlier in the header is
+/// client-controlled.
+fn client_ip(state: &AppState, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+    if !state.trust_forwarded_for {
+        return peer.ip();
+    }
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .next_back()
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse().ok())
+        .unwrap_or(peer.ip())
+}
+
+pub async fn receiver_page(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let ip = client_ip(&state, &headers, addr);
+    // Serve the page while the transfer can still be claimed (WaitingForRecipient
+    // or Reconnecting). An Active entry means another recipient already has it.
+    let claimable = state
+        .transfers
+        .get(&id)
+        .map(|entry| !matches!(entry.value(), TransferState::Active));
+
+    match claimable {
+        Some(true) => state.record_hit(ip),
+        Some(false) => {
+            return status_page(
+                StatusCode::GONE,
+                "transfer no longer available",
+                "this link has already been opened by another recipient",
+            );
+        }
+        None => {
+            tokio::time::sleep(state.record_miss(ip)).await;
+            return status_page(
+                StatusCode::NOT_FOUND,
+                "transfer not found",
+                "the link is wrong, or the sender closed their tab",
+            );
+        }
     }
 
-    let html = RECEIVER_HTML.replace("{{TRANSFER_ID}}", &id);
-    (StatusCode::OK, [(header::CONTENT_TYPE, "text/html")], html)
+    let html = RECEIVER_HTML.replace("{{CONFIG}}", &page_config(&state, &id));
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/html"), (header::CACHE_CONTROL, NO_STORE)],
+        html,
+    )
 }
 
 pub async fn transfer_info(
     Path(id): Path<String>,
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    match state.transfers.get(&id) {
-        Some(entry) => match entry.value() {
+    let ip = client_ip(&state, &headers, addr);
+    if !state.metadata_api {
+        return no_store(StatusCode::NOT_FOUND.into_response());
+    }
+    if let Some(entry) = state.transfers.get(&id) {
+        state.record_hit(ip);
+        return no_store(match entry.value() {
             TransferState::WaitingForRecipient { metadata, .. }
             | TransferState::Reconnecting { metadata, .. } => {
                 (StatusCode::OK, Json(serde_json::json!({
                     "filename": metadata.filename,
                     "size": metadata.size,
                     "mime_type": metadata.mime_type,
+                    "phase": entry.phase(),
+                }))).into_response()
+            }
+            _ => StatusCode::GONE.into_response(),
+        });
+    }
+
+    // Unknown id: hold the response if this IP looks like it is enumerating ids
+    tokio::time::sleep(state.record_miss(ip)).await;
+    no_store(StatusCode::NOT_FOUND.into_response())
+}
+
+/// Metadata lookup for integrations: `sig` must be the hex HMAC-SHA256 of the
+/// transfer id under `FILET_META_SECRET`. Only size and MIME type are returned.
+pub async fn signed_meta(
+    Path(id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let Some(secret) = state.meta_secret.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let valid = params
+        .get("sig")
+        .and_then(|sig| hex::decode(sig).ok())
+        .is_some_and(|sig| {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+            mac.update(id.as_bytes());
+            mac.verify_slice(&sig).is_ok()
+        });
+    if !valid {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    no_store(match state.transfers.get(&id) {
+        Some(entry) => match entry.value() {
+            TransferState::WaitingForRecipient { metadata, .. }
+            | TransferState::Reconnecting { metadata, .. } => {
+                (StatusCode::OK, Json(serde_json::json!({
+                    "size": metadata.size,
+                    "mime_type": metadata.mime_type,
                 }))).into_response()
             }
             _ => StatusCode::GONE.into_response(),
         },
         None => StatusCode::NOT_FOUND.into_response(),
-    }
+    })
+}
+
+pub async fn livez() -> StatusCode {
+    StatusCode::OK
+}
+
+pub async fn readyz() -> impl IntoResponse {
+    // The relay keeps nothing on disk and has no capacity limits, so once the
+    // listener is up there is nothing that can make it unready.
+    (StatusCode::OK, Json(serde_json::json!({ "status": "ready" })))
+}
+
+/// Clients that send no subprotocol predate versioning and speak v1. Clients
+/// that offer versions must offer at least one we support.
+fn protocol_supported(headers: &HeaderMap) -> bool {
+    let mut offered = headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .peekable();
+    offered.peek().is_none() || offered.any(|p| ws::SUPPORTED_PROTOCOLS.contains(&p))
+}
+
+fn unsupported_protocol() -> Response {
+    warn!("Rejecting WebSocket upgrade: unsupported protocol version");
+    (
+        StatusCode::BAD_REQUEST,
+        format!("unsupported protocol; this server speaks {}", ws::PROTOCOL_VERSION),
+    )
+        .into_response()
+}
+
+/// A slot frees when one of the client's own transfers ends, which we can't
+/// predict; this is a polite floor for automated clients to back off by.
+const CONNECTION_RETRY_AFTER_SECS: u64 = 5;
+
+fn too_many_connections(ip: IpAddr) -> Response {
+    warn!(%ip, "Rejecting connection: per-IP limit reached");
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, CONNECTION_RETRY_AFTER_SECS.to_string())],
+        "too many connections",
+    )
+        .into_response()
+}
+
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
 }
 
 pub async fn ws_send(
     ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| ws::handle_sender(socket, state))
+) -> Response {
+    if !protocol_supported(&headers) {
+        return unsupported_protocol();
+    }
+    let ip = client_ip(&state, &headers, addr);
+    let Some(guard) = state.try_acquire_connection(ip) else {
+        return too_many_connections(ip);
+    };
+    ws.protocols(ws::SUPPORTED_PROTOCOLS)
+        .max_message_size(ws::MAX_SENDER_MESSAGE)
+        .on_upgrade(move |socket| async move {
+            ws::handle_sender(socket, state).await;
+            drop(guard);
+        })
 }
 
 pub async fn ws_recv(
     ws: WebSocketUpgrade,
     Path(id): Path<String>,
     Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
+) -> Response {
+    if !protocol_supported(&headers) {
+        return unsupported_protocol();
+    }
+    let ip = client_ip(&state, &headers, addr);
+    let Some(guard) = state.try_acquire_connection(ip) else {
+        return too_many_connections(ip);
+    };
+    // Claiming over the socket is a lookup like `/d/{id}`: tarpit misses here
+    // too, or scanners would just probe this route instead.
+    let claimable = state
+        .transfers
+        .get(&id)
+        .map(|entry| !matches!(entry.value(), TransferState::Active));
+    match claimable {
+        Some(true) => state.record_hit(ip),
+        Some(false) => {}
+        None => tokio::time::sleep(state.record_miss(ip)).await,
+    }
     let offset: u64 = params
         .get("offset")
         .and_then(|o| o.parse().ok())
         .unwrap_or(0);
-    ws.on_upgrade(move |socket| ws::handle_receiver(socket, id, state, offset))
+    ws.protocols(ws::SUPPORTED_PROTOCOLS).on_upgrade(move |socket| async move {
+        ws::handle_receiver(socket, id, state, offset).await;
+        drop(guard);
+    })
 }
@@ -1,21 +1,394 @@
 use bytes::Bytes;
 use dashmap::DashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
+
+use crate::egress::EgressLimiter;
+use crate::metrics::Metrics;
 
 pub const CHANNEL_BUFFER: usize = 16;
+pub const TRANSFER_ID_LEN: usize = 12;
+pub const DEFAULT_RECONNECT_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_FIRST_DATA_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_MAX_CONNS_PER_IP: usize = 16;
+pub const DEFAULT_TARPIT_THRESHOLD: u32 = 10;
+pub const DEFAULT_TARPIT_COOLDOWN_SECS: u64 = 300;
+pub const DEFAULT_MIME: &str = "application/octet-stream";
+pub const DEFAULT_PORT: u16 = 4010;
+pub const DEFAULT_LISTEN_BACKLOG: u32 = 1024;
+pub const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+const TARPIT_STEP: Duration = Duration::from_millis(250);
+const TARPIT_MAX_DELAY: Duration = Duration::from_secs(10);
+/// Most IPs tracked at once. Past this, new IPs go untracked until the pruner
+/// frees room, so a flood of source addresses can't grow the map without bound.
+const TARPIT_MAX_TRACKED: usize = 65_536;
+/// How often records past their cooldown are dropped.
+const TARPIT_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Read `name` from the environment, falling back to `default` when it is
+/// unset or doesn't parse.
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// On/off switch from the environment. Accepts `1`/`0`, `true`/`false`,
+/// `yes`/`no` and `on`/`off` in any case, so `0` means off here like it does
+/// for the numeric knobs. Anything else keeps `default`, with a warning.
+fn env_flag(name: &str, default: bool) -> bool {
+    let Ok(value) = std::env::var(name) else {
+        return default;
+    };
+    match value.trim().to_ascii_lowercase().as_str() {
+        "" => default,
+        "1" | "true" | "yes" | "on" => true,
+        "0" | "false" | "no" | "off" => false,
+        _ => {
+            warn!("{name}={value:?} is not a boolean, using the default ({default})");
+            default
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
     pub transfers: Arc<DashMap<String, TransferState>>,
+    pub connections: Arc<DashMap<IpAddr, usize>>,
+    /// Maximum simultaneous WebSocket connections per client IP (0 = unlimited).
+    pub max_conns_per_ip: usize,
+    /// HMAC key for signed `/api/meta/{id}` lookups; the endpoint is off when unset.
+    pub meta_secret: Option<Arc<[u8]>>,
+    pub probes: Arc<DashMap<IpAddr, ProbeRecord>>,
+    /// Unknown-id lookups an IP may make before responses are delayed (0 = off).
+    pub tarpit_threshold: u32,
+    pub tarpit_cooldown: Duration,
+    /// Key per-IP limits on `X-Forwarded-For` instead of the TCP peer.
+    pub trust_forwarded_for: bool,
+    pub ids: Arc<IdGen>,
+    /// How long a transfer stays in `Reconnecting` waiting for the recipient.
+    pub reconnect_timeout: Duration,
+    /// Whether `/api/transfer/{id}` reveals filename and size to anyone with the id.
+    pub metadata_api: bool,
+    /// Absolute cap on a single recipient connection, regardless of activity.
+    pub max_transfer_duration: Option<Duration>,
+    /// How long a recipient waits for the sender's first frame after claiming.
+    pub first_data_timeout: Option<Duration>,
+    /// MIME type used when the sender's browser reports none.
+    pub default_mime: Arc<str>,
+    /// Shared cap on recipient-bound bytes per second; unlimited when `None`.
+    pub egress: Option<Arc<EgressLimiter>>,
+    pub metrics: Arc<Metrics>,
+}
+
+pub struct ProbeRecord {
+    misses: u32,
+    last_miss: Instant,
+}
+
+/// Everything `AppState` is built from. `from_env` is what the binary uses;
+/// `Default` gives the documented defaults without touching the environment.
+#[derive(Clone)]
+pub struct AppConfig {
+    pub max_conns_per_ip: usize,
+    pub meta_secret: Option<Vec<u8>>,
+    pub tarpit_threshold: u32,
+    pub tarpit_cooldown: Duration,
+    pub trust_forwarded_for: bool,
+    /// Makes transfer ids deterministic; for tests only.
+    pub token_seed: Option<u64>,
+    pub reconnect_timeout: Duration,
+    pub metadata_api: bool,
+    pub max_transfer_duration: Option<Duration>,
+    pub first_data_timeout: Option<Duration>,
+    pub default_mime: String,
+    /// Bytes per second across all recipients.
+    pub total_egress: Option<u64>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            max_conns_per_ip: DEFAULT_MAX_CONNS_PER_IP,
+            meta_secret: None,
+            tarpit_threshold: DEFAULT_TARPIT_THRESHOLD,
+            tarpit_cooldown: Duration::from_secs(DEFAULT_TARPIT_COOLDOWN_SECS),
+            trust_forwarded_for: false,
+            token_seed: None,
+            reconnect_timeout: Duration::from_secs(DEFAULT_RECONNECT_TIMEOUT_SECS),
+            metadata_api: true,
+            max_transfer_duration: None,
+            first_data_timeout: Some(Duration::from_secs(DEFAULT_FIRST_DATA_TIMEOUT_SECS)),
+            default_mime: DEFAULT_MIME.to_string(),
+            total_egress: None,
+        }
+    }
+}
+
+impl AppConfig {
+    /// Read the `FILET_*` variables, keeping the default for anything unset or
+    /// unparsable.
+    pub fn from_env() -> Self {
+        let d = Self::default();
+        Self {
+            max_conns_per_ip: env_or("FILET_MAX_CONNS_PER_IP", d.max_conns_per_ip),
+            meta_secret: std::env::var("FILET_META_SECRET")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(String::into_bytes),
+            tarpit_threshold: env_or("FILET_TARPIT_THRESHOLD", d.tarpit_threshold),
+            tarpit_cooldown: env_secs("FILET_TARPIT_COOLDOWN", d.tarpit_cooldown),
+            trust_forwarded_for: env_flag("FILET_TRUST_FORWARDED_FOR", d.trust_forwarded_for),
+            token_seed: std::env::var("FILET_TOKEN_SEED")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            reconnect_timeout: env_secs("FILET_RECONNECT_TIMEOUT", d.reconnect_timeout),
+            metadata_api: env_flag("FILET_METADATA_API", d.metadata_api),
+            max_transfer_duration: env_secs_opt(
+                "FILET_MAX_TRANSFER_DURATION",
+                d.max_transfer_duration,
+            ),
+            first_data_timeout: env_secs_opt("FILET_FIRST_DATA_TIMEOUT", d.first_data_timeout),
+            default_mime: std::env::var("FILET_DEFAULT_MIME")
+                .ok()
+                .map(|m| m.trim().to_string())
+                .filter(|m| !m.is_empty())
+                .filter(|m| {
+                    let valid = is_media_type(m);
+                    if !valid {
+                        warn!("FILET_DEFAULT_MIME={m:?} is not a media type, using {DEFAULT_MIME}");
+                    }
+                    valid
+                })
+                .unwrap_or(d.default_mime),
+            total_egress: Some(env_or("FILET_TOTAL_EGRESS", d.total_egress.unwrap_or(0)))
+                .filter(|&rate| rate > 0),
+        }
+    }
+}
+
+/// Listener and process settings, read alongside `AppConfig`. These shape how
+/// `main` serves the app rather than what the handlers do, so they stay out of
+/// `AppState`.
+#[derive(Clone)]
+pub struct ServerConfig {
+    /// Kept as given so `main` can report a bad `PORT` instead of silently
+    /// binding the default.
+    pub port: String,
+    pub listen_backlog: u32,
+    pub tcp_nodelay: bool,
+    pub shutdown_timeout: Duration,
+    /// Print a one-line JSON startup record on stdout.
+    pub json_startup: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT.to_string(),
+            listen_backlog: DEFAULT_LISTEN_BACKLOG,
+            tcp_nodelay: true,
+            shutdown_timeout: Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT_SECS),
+            json_startup: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Read `PORT` and the listener-related `FILET_*` variables, with the same
+    /// fallback rules as `AppConfig::from_env`.
+    pub fn from_env() -> Self {
+        let d = Self::default();
+        Self {
+            port: std::env::var("PORT").unwrap_or(d.port),
+            listen_backlog: env_or("FILET_LISTEN_BACKLOG", d.listen_backlog),
+            tcp_nodelay: env_flag("FILET_TCP_NODELAY", d.tcp_nodelay),
+            shutdown_timeout: env_secs("FILET_SHUTDOWN_TIMEOUT", d.shutdown_timeout),
+            json_startup: env_flag("FILET_JSON_STARTUP", d.json_startup),
+        }
+    }
+}
+
+/// `type/subtype` made of RFC 7230 token characters, optionally followed by
+/// `;` parameters (which are passed through as-is).
+fn is_media_type(value: &str) -> bool {
+    let is_token = |s: &str| {
+        !s.is_empty()
+            && s.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$&^_.+-".contains(&b))
+    };
+    let essence = value.split(';').next().unwrap_or_default().trim();
+    value.bytes().all(|b| !b.is_ascii_control())
+        && essence
+            .split_once('/')
+            .is_some_and(|(ty, sub)| is_token(ty) && is_token(sub))
+}
+
+fn env_secs(name: &str, default: Duration) -> Duration {
+    Duration::from_secs(env_or(name, default.as_secs()))
+}
+
+/// Like `env_secs`, with `0` meaning "no limit".
+fn env_secs_opt(name: &str, default: Option<Duration>) -> Option<Duration> {
+    Some(env_or(name, default.map_or(0, |d| d.as_secs())))
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs)
 }
 
 impl AppState {
-    pub fn new() -> Self {
+    pub fn new(config: AppConfig) -> Self {
         Self {
             transfers: Arc::new(DashMap::new()),
+            connections: Arc::new(DashMap::new()),
+            max_conns_per_ip: config.max_conns_per_ip,
+            meta_secret: config.meta_secret.map(Arc::from),
+            probes: Arc::new(DashMap::new()),
+            tarpit_threshold: config.tarpit_threshold,
+            tarpit_cooldown: config.tarpit_cooldown,
+            trust_forwarded_for: config.trust_forwarded_for,
+            ids: Arc::new(IdGen::new(config.token_seed)),
+            reconnect_timeout: config.reconnect_timeout,
+            metadata_api: config.metadata_api,
+            max_transfer_duration: config.max_transfer_duration,
+            first_data_timeout: config.first_data_timeout,
+            default_mime: Arc::from(config.default_mime),
+            egress: config
+                .total_egress
+                .map(|rate| Arc::new(EgressLimiter::new(rate))),
+            metrics: Arc::new(Metrics::default()),
         }
     }
+
+    pub fn from_env() -> Self {
+        Self::new(AppConfig::from_env())
+    }
+
+    /// Record a lookup of an unknown transfer id from `ip` and return how long
+    /// to hold the response. The delay grows with each miss past the threshold
+    /// and resets after `tarpit_cooldown` without misses.
+    pub fn record_miss(&self, ip: IpAddr) -> Duration {
+        if self.tarpit_threshold == 0 || ip.is_loopback() {
+            return Duration::ZERO;
+        }
+        let now = Instant::now();
+        if self.probes.len() >= TARPIT_MAX_TRACKED && !self.probes.contains_key(&ip) {
+            return Duration::ZERO;
+        }
+        let mut record = self.probes.entry(ip).or_insert(ProbeRecord {
+            misses: 0,
+            last_miss: now,
+        });
+        if now.duration_since(record.last_miss) >= self.tarpit_cooldown {
+            record.misses = 0;
+        }
+        record.misses = record.misses.saturating_add(1);
+        record.last_miss = now;
+        let over = record.misses.saturating_sub(self.tarpit_threshold);
+        (TARPIT_STEP * over).min(TARPIT_MAX_DELAY)
+    }
+
+    /// Forget an IP's misses once it looks up a transfer that exists.
+    pub fn record_hit(&self, ip: IpAddr) {
+        self.probes.remove(&ip);
+    }
+
+    /// Periodically forget IPs whose tarpit has cooled down. Pruning here
+    /// rather than on each miss keeps `record_miss` O(1).
+    pub fn spawn_probe_pruner(&self) {
+        let probes = self.probes.clone();
+        let cooldown = self.tarpit_cooldown;
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(TARPIT_PRUNE_INTERVAL);
+            loop {
+                timer.tick().await;
+                let now = Instant::now();
+                probes.retain(|_, r| now.duration_since(r.last_miss) < cooldown);
+            }
+        });
+    }
+
+    /// Reserve a connection slot for `ip`, or `None` if it is already at the
+    /// limit. Loopback clients are never limited.
+    pub fn try_acquire_connection(&self, ip: IpAddr) -> Option<ConnectionGuard> {
+        if self.max_conns_per_ip > 0 && !ip.is_loopback() {
+            let mut count = self.connections.entry(ip).or_insert(0);
+            if *count >= self.max_conns_per_ip {
+                return None;
+            }
+            *count += 1;
+        } else {
+            *self.connections.entry(ip).or_insert(0) += 1;
+        }
+        Some(ConnectionGuard {
+            connections: self.connections.clone(),
+            ip,
+        })
+    }
+}
+
+/// Transfer id source. Random by default; with a seed (`FILET_TOKEN_SEED`) it
+/// yields the same id sequence on every run so tests can assert on ids.
+pub struct IdGen {
+    seed: Option<u64>,
+    counter: AtomicU64,
+}
+
+impl IdGen {
+    pub fn new(seed: Option<u64>) -> Self {
+        Self {
+            seed,
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_seeded(&self) -> bool {
+        self.seed.is_some()
+    }
+
+    pub fn next_id(&self) -> String {
+        let Some(seed) = self.seed else {
+            return nanoid::nanoid!(TRANSFER_ID_LEN);
+        };
+        // splitmix64 stream keyed on (seed, n); SAFE has 64 symbols, so 6 bits per char
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        let mut x = mix64(seed ^ mix64(n.wrapping_add(1)));
+        (0..TRANSFER_ID_LEN)
+            .map(|_| {
+                x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                nanoid::alphabet::SAFE[(mix64(x) & 63) as usize]
+            })
+            .collect()
+    }
+}
+
+/// splitmix64 finalizer.
+fn mix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Releases a per-IP connection slot when dropped.
+pub struct ConnectionGuard {
+    connections: Arc<DashMap<IpAddr, usize>>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        // Drop the entry once the last connection goes so the map doesn't
+        // grow with every client ever seen.
+        self.connections.remove_if_mut(&self.ip, |_, count| {
+            *count = count.saturating_sub(1);
+            *count == 0
+        });
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -38,14 +411,80 @@ pub enum TransferState {
     Active,
 }
 
+impl TransferState {
+    /// Name of the phase as shown to recipient pages.
+    pub fn phase(&self) -> &'static str {
+        match self {
+            TransferState::WaitingForRecipient { .. } => "waiting",
+            TransferState::Reconnecting { .. } => "paused",
+            TransferState::Active => "active",
+        }
+    }
+}
+
 pub struct RecipientLink {
     pub data_tx: mpsc::Sender<RelayMessage>,
-    pub cancel_rx: mpsc::Receiver<()>,
+    pub cancel_rx: mpsc::Receiver<RecipientSignal>,
+    /// Fires once the recipient's socket has accepted the final `done` frame.
+    pub delivered_rx: oneshot::Receiver<()>,
     pub resume_offset: u64,
 }
 
 pub enum RelayMessage {
     Data(Bytes),
+    /// The sender acknowledged a restart; everything after this starts from offset 0.
+    Restarted,
     Finished,
-    Error(String),
+    Cancelled(CancelReason),
+}
+
+/// Recipient -> sender signals, carried on the link's cancel channel.
+pub enum RecipientSignal {
+    /// The recipient went away (closed or failed a send) and may reconnect.
+    Gone,
+    /// The recipient discarded what it has and wants the file from the start.
+    Restart,
+    /// The recipient's connection was ended for good (it has been told why);
+    /// the transfer is cancelled rather than held open for a reconnect.
+    Cancel(CancelReason),
+}
+
+/// Why a transfer was abandoned. Serialized as a stable snake_case code in the
+/// `reason` field of `cancelled` frames so clients can branch on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelReason {
+    SenderDisconnected,
+    RecipientDisconnected,
+    ReconnectTimeout,
+    MaxDurationExceeded,
+    SenderTimeout,
+    SenderProtocolError,
+    TooManyRestarts,
+    RestartTooLate,
+}
+
+impl CancelReason {
+    pub fn message(self) -> &'static str {
+        match self {
+            CancelReason::SenderDisconnected => "Sender disconnected",
+            CancelReason::RecipientDisconnected => "Recipient disconnected",
+            CancelReason::ReconnectTimeout => "Recipient did not reconnect in time",
+            CancelReason::MaxDurationExceeded => "Transfer exceeded the maximum allowed duration",
+            CancelReason::SenderTimeout => "Sender did not start sending",
+            CancelReason::SenderProtocolError => "Sender sent invalid data",
+            CancelReason::TooManyRestarts => "Transfer was restarted too many times",
+            CancelReason::RestartTooLate => "Sender finished before it could restart",
+        }
+    }
+
+    /// JSON `cancelled` frame carrying this reason.
+    pub fn to_frame(self) -> String {
+        serde_json::json!({
+            "type": "cancelled",
+            "reason": self,
+            "error": self.message(),
+        })
+        .to_string()
+    }
 }
@@ -1,12 +1,34 @@
 use axum::extract::ws::{Message, WebSocket};
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, oneshot};
-use tracing::{info, warn};
+use tracing::{info, instrument, warn};
 
+use crate::metrics::{Metrics, ReconnectOutcome};
 use crate::state::*;
 
-const RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Current wire protocol, negotiated via `Sec-WebSocket-Protocol`.
+pub const PROTOCOL_VERSION: &str = "filet.v1";
+/// Every protocol version this server can speak, newest first.
+pub const SUPPORTED_PROTOCOLS: [&str; 1] = [PROTOCOL_VERSION];
+
+/// Largest frame accepted from a sender: one 1 MB chunk plus IV/tag, with headroom.
+pub const MAX_SENDER_MESSAGE: usize = 2 * 1024 * 1024;
+/// The `SenderInit` frame is a few hundred bytes; anything near this is abuse.
+const MAX_METADATA_SIZE: usize = 64 * 1024;
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the relay channel may stay full before we log a slow recipient.
+const BACKPRESSURE_WARN_AFTER: Duration = Duration::from_secs(5);
+/// Per-chunk framing the sender page adds around each plaintext chunk (IV + GCM tag).
+const FRAME_OVERHEAD: u64 = 12 + 16;
+/// Text frames during relay are control messages (`done`); nothing legitimate is this big.
+const MAX_CONTROL_FRAME: usize = 1024;
+/// Unrecognised text frames tolerated during relay before the sender is cut off.
+const MAX_SPURIOUS_FRAMES: u32 = 8;
+/// Full re-sends a recipient may ask for over the life of a transfer.
+pub const MAX_RESTARTS: u32 = 3;
+const MAX_FILENAME_LEN: usize = 255;
 
 #[derive(serde::Deserialize)]
 struct SenderInit {
@@ -25,40 +47,92 @@ struct SenderResponse {
     error: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     offset: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<CancelReason>,
 }
 
+/// Check a sender-supplied filename before it is shown to recipients and used as
+/// the download name. Returns the name with surrounding whitespace trimmed.
+fn validate_filename(name: &str) -> Result<String, &'static str> {
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." {
+        return Err("name is empty");
+    }
+    if name.len() > MAX_FILENAME_LEN {
+        return Err("name is too long");
+    }
+    if name.contains(['/', '\\']) {
+        return Err("name contains a path separator");
+    }
+    if name.chars().any(char::is_control) {
+        return Err("name contains control characters");
+    }
+    Ok(name.to_string())
+}
+
+#[instrument(name = "sender", skip_all, fields(transfer_id))]
 pub async fn handle_sender(socket: WebSocket, state: AppState) {
     let (mut ws_tx, mut ws_rx) = socket.split();
 
-    // Step 1: Wait for metadata from sender
+    // Step 1: Wait for metadata from sender, bounded in size and time so a
+    // client that connects and never identifies itself doesn't hold the task.
+    let deadline = tokio::time::Instant::now() + METADATA_TIMEOUT;
     let metadata = loop {
-        match ws_rx.next().await {
+        let Ok(msg) = tokio::time::timeout_at(deadline, ws_rx.next()).await else {
+            let _ = ws_tx
+                .send(Message::Text(
+                    serde_json::to_string(&SenderResponse {
+                        r#type: "error".into(),
+                        id: None,
+                        error: Some("Timed out waiting for metadata".into()),
+                        offset: None,
+                        reason: None,
+                    })
+                    .unwrap()
+                    .into(),
+                ))
+                .await;
+            let _ = ws_tx.send(Message::Close(None)).await;
+            return;
+        };
+        match msg {
             Some(Ok(Message::Text(text))) => {
-                match serde_json::from_str::<SenderInit>(&text) {
-                    Ok(init) => {
-                        break FileMetadata {
-                            filename: init.filename,
-                            size: init.size,
-                            mime_type: if init.mime_type.is_empty() {
-                                "application/octet-stream".to_string()
-                            } else {
-                                init.mime_type
-                            },
-                        };
-                    }
+                let parsed = if text.len() > MAX_METADATA_SIZE {
+                    Err(format!("Metadata too large (max {MAX_METADATA_SIZE} bytes)"))
+                } else {
+                    serde_json::from_str::<SenderInit>(&text)
+                        .map_err(|e| format!("Invalid metadata: {e}"))
+                }
+                .and_then(|init| {
+                    let filename = validate_filename(&init.filename)
+                        .map_err(|e| format!("Invalid filename: {e}"))?;
+                    Ok(FileMetadata {
+                        filename,
+                        size: init.size,
+                        mime_type: if init.mime_type.is_empty() {
+                            state.default_mime.to_string()
+                        } else {
+                            init.mime_type
+                        },
+                    })
+                });
+                match parsed {
+                    Ok(metadata) => break metadata,
                     Err(e) => {
                         let _ = ws_tx
                             .send(Message::Text(
                                 serde_json::to_string(&SenderResponse {
                                     r#type: "error".into(),
                                     id: None,
-                                    error: Some(format!("Invalid metadata: {e}")),
+                                    error: Some(e),
                                     offset: None,
+                                    reason: None,
                                 })
                                 .unwrap()
                                 .into(),
                             ))
                             .await;
+                        let _ = ws_tx.send(Message::Close(None)).await;
                         return;
                     }
                 }
@@ -70,7 +144,8 @@ pub async fn handle_sender(socket: WebSocket, state: AppState) {
 
     // Step 2: Create transfer entry with oneshot for recipient signaling
     let (recipient_tx, recipient_rx) = oneshot::channel::<RecipientLink>();
-    let id = nanoid::nanoid!(12);
+    let id = state.ids.next_id();
+    tracing::Span::current().record("transfer_id", tracing::field::display(&id));
 
     state.transfers.insert(
         id.clone(),
@@ -88,13 +163,14 @@ pub async fn handle_sender(socket: WebSocket, state: AppState) {
                 id: Some(id.clone()),
                 error: None,
                 offset: None,
+                reason: None,
             })
             .unwrap()
             .into(),
         ))
         .await;
 
-    info!(transfer_id = %id, filename = %metadata.filename, size = metadata.size, "Transfer created, waiting for recipient");
+    info!(filename = %metadata.filename, size = metadata.size, "Transfer created, waiting for recipient");
 
     // Step 3: Wait for recipient to connect (or sender to disconnect).
     // Loop so we can ignore keepalive messages and send periodic pings to
@@ -109,7 +185,7 @@ pub async fn handle_sender(socket: WebSocket, state: AppState) {
                     match result {
                         Ok(link) => break link,
                         Err(_) => {
-                            warn!(transfer_id = %id, "Recipient channel dropped");
+                            warn!("Recipient channel dropped");
                             state.transfers.remove(&id);
                             return;
                         }
@@ -118,7 +194,7 @@ pub async fn handle_sender(socket: WebSocket, state: AppState) {
                 msg = ws_rx.next() => {
                     match msg {
                         Some(Ok(Message::Close(_))) | None => {
-                            info!(transfer_id = %id, "Sender disconnected while waiting");
+                            info!("Sender disconnected while waiting");
                             state.transfers.remove(&id);
                             return;
                         }
@@ -140,23 +216,87 @@ pub async fn handle_sender(socket: WebSocket, state: AppState) {
                 id: None,
                 error: None,
                 offset: None,
+                reason: None,
             })
             .unwrap()
             .into(),
         ))
         .await;
 
-    info!(transfer_id = %id, "Transfer started");
+    info!("Transfer started");
 
     // Step 5: Relay loop with reconnection support
+    let mut restarts: u32 = 0;
     loop {
-        let data_tx = recipient_link.data_tx;
-        let mut cancel_rx = recipient_link.cancel_rx;
-
-        let relay_result = relay_data(&mut ws_rx, &mut ws_tx, &data_tx, &mut cancel_rx, &id).await;
+        let relay_result = relay_data(
+            &mut ws_rx,
+            &mut ws_tx,
+            &mut recipient_link,
+            metadata.size,
+            &mut restarts,
+            &state.metrics,
+        )
+        .await;
 
         match relay_result {
-            RelayResult::Done | RelayResult::SenderDisconnected => break,
+            RelayResult::Done => {
+                // Tell the sender once the recipient actually has everything,
+                // not just once we queued the last frame. Keep pinging while
+                // a slow recipient drains, and stop if the sender goes away.
+                let mut ended_by = None;
+                let delivered = {
+                    let mut ping_timer = tokio::time::interval(Duration::from_secs(15));
+                    ping_timer.tick().await;
+                    loop {
+                        tokio::select! {
+                            result = &mut recipient_link.delivered_rx => break result,
+                            // Keep the signal slot drained: a `restart` that came too
+                            // late would otherwise block the recipient's `Gone` forever.
+                            // Its departure shows up as `delivered_rx` failing.
+                            Some(signal) = recipient_link.cancel_rx.recv() => {
+                                if let RecipientSignal::Cancel(reason) = signal {
+                                    ended_by = Some(reason);
+                                }
+                            }
+                            msg = ws_rx.next() => {
+                                match msg {
+                                    Some(Ok(Message::Close(_))) | None => {
+                                        info!("Sender disconnected before delivery was confirmed");
+                                        state.transfers.remove(&id);
+                                        return;
+                                    }
+                                    _ => {} // keepalive pong — ignore
+                                }
+                            }
+                            _ = ping_timer.tick() => {
+                                let _ = ws_tx.send(Message::Ping(bytes::Bytes::new())).await;
+                            }
+                        }
+                    }
+                };
+                let (r#type, reason) = match delivered {
+                    Ok(()) => ("delivered", None),
+                    Err(_) => (
+                        "cancelled",
+                        Some(ended_by.unwrap_or(CancelReason::RecipientDisconnected)),
+                    ),
+                };
+                let _ = ws_tx
+                    .send(Message::Text(
+                        serde_json::to_string(&SenderResponse {
+                            r#type: r#type.into(),
+                            id: None,
+                            error: reason.map(|r| r.message().into()),
+                            offset: None,
+                            reason,
+                        })
+                        .unwrap()
+                        .into(),
+                    ))
+                    .await;
+                break;
+            }
+            RelayResult::SenderDisconnected | RelayResult::Cancelled => break,
             RelayResult::RecipientDisconnected => {
                 // Recipient dropped — try to let them reconnect
                 let (new_tx, new_rx) = oneshot::channel::<RecipientLink>();
@@ -175,19 +315,21 @@ pub async fn handle_sender(socket: WebSocket, state: AppState) {
                             id: None,
                             error: None,
                             offset: None,
+                            reason: None,
                         })
                         .unwrap()
                         .into(),
                     ))
                     .await;
 
-                info!(transfer_id = %id, "Recipient disconnected, waiting for reconnect");
+                info!("Recipient disconnected, waiting for reconnect");
+                let paused_at = Instant::now();
 
                 // Wait for reconnect, timeout, or sender disconnect.
                 // Loop to ignore keepalive messages and send pings to the sender.
-                let new_link = {
+                let new_link: Result<RecipientLink, CancelReason> = {
                     tokio::pin!(new_rx);
-                    let sleep = tokio::time::sleep(RECONNECT_TIMEOUT);
+                    let sleep = tokio::time::sleep(state.reconnect_timeout);
                     tokio::pin!(sleep);
                     let mut ping_timer = tokio::time::interval(Duration::from_secs(15));
                     ping_timer.tick().await;
@@ -195,21 +337,21 @@ pub async fn handle_sender(socket: WebSocket, state: AppState) {
                         tokio::select! {
                             result = &mut new_rx => {
                                 match result {
-                                    Ok(link) => break Some(link),
-                                    Err(_) => break None,
+                                    Ok(link) => break Ok(link),
+                                    Err(_) => break Err(CancelReason::RecipientDisconnected),
                                 }
                             }
                             _ = &mut sleep => {
-                                info!(transfer_id = %id, "Reconnect timeout");
+                                info!("Reconnect timeout");
                                 state.transfers.remove(&id);
-                                break None;
+                                break Err(CancelReason::ReconnectTimeout);
                             }
                             msg = ws_rx.next() => {
                                 match msg {
                                     Some(Ok(Message::Close(_))) | None => {
-                                        info!(transfer_id = %id, "Sender disconnected while waiting for reconnect");
+                                        info!("Sender disconnected while waiting for reconnect");
                                         state.transfers.remove(&id);
-                                        break None;
+                                        break Err(CancelReason::SenderDisconnected);
                                     }
                                     _ => {} // keepalive pong — ignore
                                 }
@@ -221,8 +363,14 @@ pub async fn handle_sender(socket: WebSocket, state: AppState) {
                     }
                 };
 
+                state.metrics.record_reconnect(match &new_link {
+                    Ok(_) => ReconnectOutcome::Succeeded(paused_at.elapsed()),
+                    Err(CancelReason::ReconnectTimeout) => ReconnectOutcome::TimedOut,
+                    Err(_) => ReconnectOutcome::Abandoned,
+                });
+
                 match new_link {
-                    Some(link) => {
+                    Ok(link) => {
                         // Tell sender to resume from the receiver's offset
                         let _ = ws_tx
                             .send(Message::Text(
@@ -231,26 +379,28 @@ pub async fn handle_sender(socket: WebSocket, state: AppState) {
                                     id: None,
                                     error: None,
                                     offset: Some(link.resume_offset),
+                                    reason: None,
                                 })
                                 .unwrap()
                                 .into(),
                             ))
                             .await;
 
-                        info!(transfer_id = %id, offset = link.resume_offset, "Recipient reconnected, resuming");
+                        info!(offset = link.resume_offset, "Recipient reconnected, resuming");
                         state.transfers.insert(id.clone(), TransferState::Active);
                         recipient_link = link;
                         // Continue outer loop — restart relay
                     }
-                    None => {
+                    Err(reason) => {
                         // Give up
                         let _ = ws_tx
                             .send(Message::Text(
                                 serde_json::to_string(&SenderResponse {
                                     r#type: "cancelled".into(),
                                     id: None,
-                                    error: Some("Recipient disconnected".into()),
+                                    error: Some(reason.message().into()),
                                     offset: None,
+                                    reason: Some(reason),
                                 })
                                 .unwrap()
                                 .into(),
@@ -270,50 +420,274 @@ enum RelayResult {
     Done,
     SenderDisconnected,
     RecipientDisconnected,
+    /// Both sides have already been told why the transfer ended.
+    Cancelled,
 }
 
+#[instrument(name = "relay", skip_all)]
 async fn relay_data(
     ws_rx: &mut futures_util::stream::SplitStream<WebSocket>,
-    _ws_tx: &mut futures_util::stream::SplitSink<WebSocket, Message>,
-    data_tx: &mpsc::Sender<RelayMessage>,
-    cancel_rx: &mut mpsc::Receiver<()>,
-    id: &str,
+    ws_tx: &mut futures_util::stream::SplitSink<WebSocket, Message>,
+    link: &mut RecipientLink,
+    size: u64,
+    restarts: &mut u32,
+    metrics: &Metrics,
 ) -> RelayResult {
+    let data_tx = &link.data_tx;
+    let cancel_rx = &mut link.cancel_rx;
+    let mut remaining = size.saturating_sub(link.resume_offset);
+    // Backpressure: while the bounded channel is full we stop reading the sender's
+    // socket, so TCP flow control pushes back to the sender's browser, whose
+    // pipeline waits on ws.bufferedAmount. These track how often that happens.
+    let mut stalls: u32 = 0;
+    let mut stalled_for = Duration::ZERO;
+    // Plaintext bytes relayed so far, checked against what the metadata announced
+    let mut relayed: u64 = 0;
+    let mut spurious: u32 = 0;
+    // After asking the sender to restart, frames still in flight from the old
+    // stream are dropped until it answers with `restarted`.
+    let mut awaiting_restart = false;
+
     loop {
         tokio::select! {
             msg = ws_rx.next() => {
                 match msg {
+                    Some(Ok(Message::Binary(_))) if awaiting_restart => continue,
                     Some(Ok(Message::Binary(data))) => {
-                        if data_tx.send(RelayMessage::Data(data)).await.is_err() {
-                            warn!(transfer_id = %id, "Recipient channel closed during relay");
-                            return RelayResult::RecipientDisconnected;
+                        let len = data.len() as u64;
+                        if len < FRAME_OVERHEAD {
+                            return reject_sender(ws_tx, data_tx, "Data frame too short to be an encrypted chunk").await;
+                        }
+                        relayed += len - FRAME_OVERHEAD;
+                        if relayed > remaining {
+                            return reject_sender(ws_tx, data_tx, "Data exceeds the announced file size").await;
+                        }
+                        let reserve = async {
+                            match tokio::time::timeout(BACKPRESSURE_WARN_AFTER, data_tx.reserve()).await {
+                                Ok(permit) => permit,
+                                Err(_) => {
+                                    stalls += 1;
+                                    metrics.record_stall();
+                                    warn!(
+                                        stalls,
+                                        "Relay channel full for {BACKPRESSURE_WARN_AFTER:?}, recipient is reading slower than the sender"
+                                    );
+                                    let started = Instant::now();
+                                    let permit = data_tx.reserve().await;
+                                    let waited = started.elapsed() + BACKPRESSURE_WARN_AFTER;
+                                    stalled_for += waited;
+                                    metrics.record_stall_time(waited);
+                                    info!(
+                                        waited_ms = waited.as_millis() as u64,
+                                        "Relay channel drained"
+                                    );
+                                    permit
+                                }
+                            }
+                        };
+                        // A recipient that dies while the channel is full must stop us
+                        // here, not after the sender's next frame.
+                        let permit = tokio::select! {
+                            permit = reserve => permit,
+                            signal = cancel_rx.recv() => {
+                                match on_recipient_signal(signal, ws_tx, data_tx, restarts, &mut awaiting_restart).await {
+                                    Some(result) => return result,
+                                    // Restarting: this frame belongs to the old stream
+                                    None => continue,
+                                }
+                            }
+                        };
+                        match permit {
+                            Ok(permit) => permit.send(RelayMessage::Data(data)),
+                            Err(_) => {
+                                warn!("Recipient channel closed during relay");
+                                return RelayResult::RecipientDisconnected;
+                            }
                         }
                     }
                     Some(Ok(Message::Text(text))) => {
-                        if let Ok(val) = serde_json::from_str::<serde_json::Value>(&*text) {
-                            if val.get("type").and_then(|t| t.as_str()) == Some("done") {
+                        if text.len() > MAX_CONTROL_FRAME {
+                            return reject_sender(ws_tx, data_tx, "Control frame too large").await;
+                        }
+                        let r#type = control_type(&text);
+                        if awaiting_restart {
+                            if r#type.as_deref() == Some("restarted") {
+                                awaiting_restart = false;
+                                relayed = 0;
+                                remaining = size;
+                                if data_tx.send(RelayMessage::Restarted).await.is_err() {
+                                    return RelayResult::RecipientDisconnected;
+                                }
+                                info!(restarts = *restarts, "Sender restarted from offset 0");
+                            } else if r#type.as_deref() == Some("done") {
+                                // The sender finished before it saw `restart` and won't
+                                // replay. Pass the `done` on so the recipient stops waiting,
+                                // but don't let the sender think the cut-short stream landed.
+                                warn!(restarts = *restarts, "Sender finished before restarting, cancelling transfer");
+                                let reason = CancelReason::RestartTooLate;
                                 let _ = data_tx.send(RelayMessage::Finished).await;
-                                info!(transfer_id = %id, "Transfer complete");
-                                return RelayResult::Done;
+                                let _ = ws_tx
+                                    .send(Message::Text(
+                                        serde_json::to_string(&SenderResponse {
+                                            r#type: "cancelled".into(),
+                                            id: None,
+                                            error: Some(reason.message().into()),
+                                            offset: None,
+                                            reason: Some(reason),
+                                        })
+                                        .unwrap()
+                                        .into(),
+                                    ))
+                                    .await;
+                                return RelayResult::Cancelled;
+                            }
+                            continue;
+                        }
+                        if r#type.as_deref() == Some("done") {
+                            if relayed != remaining {
+                                return reject_sender(ws_tx, data_tx, "Data ended before the announced file size").await;
                             }
+                            let _ = data_tx.send(RelayMessage::Finished).await;
+                            info!(
+                                stalls,
+                                stalled_ms = stalled_for.as_millis() as u64,
+                                "Transfer complete"
+                            );
+                            return RelayResult::Done;
+                        }
+                        spurious += 1;
+                        if spurious > MAX_SPURIOUS_FRAMES {
+                            return reject_sender(ws_tx, data_tx, "Too many unexpected control frames").await;
                         }
                     }
                     Some(Ok(Message::Close(_))) | None => {
-                        let _ = data_tx.send(RelayMessage::Error("Sender disconnected".into())).await;
-                        warn!(transfer_id = %id, "Sender disconnected during transfer");
+                        let _ = data_tx.send(RelayMessage::Cancelled(CancelReason::SenderDisconnected)).await;
+                        warn!("Sender disconnected during transfer");
                         return RelayResult::SenderDisconnected;
                     }
                     _ => continue,
                 }
             }
-            _ = cancel_rx.recv() => {
-                info!(transfer_id = %id, "Recipient disconnected during transfer");
-                return RelayResult::RecipientDisconnected;
+            signal = cancel_rx.recv() => {
+                if let Some(result) = on_recipient_signal(signal, ws_tx, data_tx, restarts, &mut awaiting_restart).await {
+                    return result;
+                }
             }
         }
     }
 }
 
+/// Act on a recipient signal mid-relay. `None` means keep relaying.
+async fn on_recipient_signal(
+    signal: Option<RecipientSignal>,
+    ws_tx: &mut futures_util::stream::SplitSink<WebSocket, Message>,
+    data_tx: &mpsc::Sender<RelayMessage>,
+    restarts: &mut u32,
+    awaiting_restart: &mut bool,
+) -> Option<RelayResult> {
+    match signal {
+        Some(RecipientSignal::Restart) if *awaiting_restart => None,
+        Some(RecipientSignal::Restart) if *restarts >= MAX_RESTARTS => {
+            warn!(restarts = *restarts, "Restart limit reached, cancelling transfer");
+            let reason = CancelReason::TooManyRestarts;
+            let _ = data_tx.send(RelayMessage::Cancelled(reason)).await;
+            let _ = ws_tx
+                .send(Message::Text(
+                    serde_json::to_string(&SenderResponse {
+                        r#type: "cancelled".into(),
+                        id: None,
+                        error: Some(reason.message().into()),
+                        offset: None,
+                        reason: Some(reason),
+                    })
+                    .unwrap()
+                    .into(),
+                ))
+                .await;
+            Some(RelayResult::Cancelled)
+        }
+        Some(RecipientSignal::Restart) => {
+            *restarts += 1;
+            *awaiting_restart = true;
+            info!(restarts = *restarts, "Recipient requested a restart");
+            let _ = ws_tx
+                .send(Message::Text(
+                    serde_json::to_string(&SenderResponse {
+                        r#type: "restart".into(),
+                        id: None,
+                        error: None,
+                        offset: None,
+                        reason: None,
+                    })
+                    .unwrap()
+                    .into(),
+                ))
+                .await;
+            None
+        }
+        Some(RecipientSignal::Cancel(reason)) => {
+            info!(?reason, "Recipient connection ended, cancelling transfer");
+            let _ = ws_tx
+                .send(Message::Text(
+                    serde_json::to_string(&SenderResponse {
+                        r#type: "cancelled".into(),
+                        id: None,
+                        error: Some(reason.message().into()),
+                        offset: None,
+                        reason: Some(reason),
+                    })
+                    .unwrap()
+                    .into(),
+                ))
+                .await;
+            Some(RelayResult::Cancelled)
+        }
+        Some(RecipientSignal::Gone) | None => {
+            info!("Recipient disconnected during transfer");
+            Some(RelayResult::RecipientDisconnected)
+        }
+    }
+}
+
+/// The `type` of a JSON control frame, if it has one.
+fn control_type(text: &str) -> Option<String> {
+    let val: serde_json::Value = serde_json::from_str(text).ok()?;
+    Some(val.get("type")?.as_str()?.to_owned())
+}
+
+/// Tells the recipient page which phase of the transfer it is in.
+fn phase_frame(phase: &str) -> String {
+    serde_json::json!({ "type": "phase", "phase": phase }).to_string()
+}
+
+/// Protocol violation from the sender: tell both ends and drop the transfer.
+async fn reject_sender(
+    ws_tx: &mut futures_util::stream::SplitSink<WebSocket, Message>,
+    data_tx: &mpsc::Sender<RelayMessage>,
+    error: &str,
+) -> RelayResult {
+    warn!(error, "Sender violated the relay protocol");
+    let _ = data_tx
+        .send(RelayMessage::Cancelled(CancelReason::SenderProtocolError))
+        .await;
+    let _ = ws_tx
+        .send(Message::Text(
+            serde_json::to_string(&SenderResponse {
+                r#type: "error".into(),
+                id: None,
+                error: Some(error.into()),
+                offset: None,
+                reason: None,
+            })
+            .unwrap()
+            .into(),
+        ))
+        .await;
+    let _ = ws_tx.send(Message::Close(None)).await;
+    RelayResult::SenderDisconnected
+}
+
+#[instrument(name = "recipient", skip_all, fields(transfer_id = %id))]
 pub async fn handle_receiver(socket: WebSocket, id: String, state: AppState, resume_offset: u64) {
     let (mut ws_tx, mut ws_rx) = socket.split();
 
@@ -348,7 +722,8 @@ pub async fn handle_receiver(socket: WebSocket, id: String, state: AppState, res
 
     // Create the relay channel
     let (data_tx, mut data_rx) = mpsc::channel::<RelayMessage>(CHANNEL_BUFFER);
-    let (cancel_tx, cancel_rx) = mpsc::channel::<()>(1);
+    let (cancel_tx, cancel_rx) = mpsc::channel::<RecipientSignal>(1);
+    let (delivered_tx, delivered_rx) = oneshot::channel::<()>();
 
     // Send metadata to recipient
     let _ = ws_tx
@@ -358,25 +733,27 @@ pub async fn handle_receiver(socket: WebSocket, id: String, state: AppState, res
                 "filename": metadata.filename,
                 "size": metadata.size,
                 "mime_type": metadata.mime_type,
+                "reconnect_timeout": state.reconnect_timeout.as_secs(),
             }))
             .unwrap()
             .into(),
         ))
         .await;
+    // Connected, but nothing flows until the sender acts on start/resume
+    let _ = ws_tx.send(Message::Text(phase_frame("waiting").into())).await;
 
     // Signal the sender that recipient is ready
     let link = RecipientLink {
         data_tx,
         cancel_rx,
+        delivered_rx,
         resume_offset,
     };
 
     if recipient_tx.send(link).is_err() {
         let _ = ws_tx
             .send(Message::Text(
-                r#"{"type":"error","error":"Sender disconnected"}"#
-                    .to_string()
-                    .into(),
+                CancelReason::SenderDisconnected.to_frame().into(),
             ))
             .await;
         return;
@@ -385,39 +762,105 @@ pub async fn handle_receiver(socket: WebSocket, id: String, state: AppState, res
     // Mark as active
     state.transfers.insert(id.clone(), TransferState::Active);
 
-    info!(transfer_id = %id, resume_offset, "Recipient connected, relaying data");
+    info!(resume_offset, "Recipient connected, relaying data");
+
+    // Cap the connection's total lifetime so a recipient can't hold the relay
+    // open indefinitely by trickling reads
+    let max_duration = state.max_transfer_duration;
+    let lifetime = async move {
+        match max_duration {
+            Some(d) => tokio::time::sleep(d).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(lifetime);
+
+    // Guard against a sender that was told to start but never streams
+    let first_data_timeout = state.first_data_timeout;
+    let first_data = async move {
+        match first_data_timeout {
+            Some(d) => tokio::time::sleep(d).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(first_data);
+    let mut got_data = false;
+
+    // A frame waiting on the shared egress budget. The wait is its own branch
+    // rather than part of the data arm, so a recipient queued behind others
+    // still notices its own close, lifetime cap or restart request.
+    let mut pending: Option<(bytes::Bytes, BoxFuture<'static, ()>)> = None;
 
     // Relay data from mpsc channel to recipient WS
     loop {
         tokio::select! {
-            msg = data_rx.recv() => {
+            _ = &mut first_data, if !got_data => {
+                warn!(?first_data_timeout, "No data from sender, closing recipient");
+                let _ = ws_tx.send(Message::Text(
+                    CancelReason::SenderTimeout.to_frame().into()
+                )).await;
+                let _ = cancel_tx.send(RecipientSignal::Cancel(CancelReason::SenderTimeout)).await;
+                break;
+            }
+            _ = &mut lifetime => {
+                warn!(?max_duration, "Recipient connection exceeded maximum duration, closing");
+                let _ = ws_tx.send(Message::Text(
+                    CancelReason::MaxDurationExceeded.to_frame().into()
+                )).await;
+                let _ = cancel_tx.send(RecipientSignal::Cancel(CancelReason::MaxDurationExceeded)).await;
+                break;
+            }
+            _ = async { pending.as_mut().unwrap().1.as_mut().await }, if pending.is_some() => {
+                let (data, _) = pending.take().unwrap();
+                if ws_tx.send(Message::Binary(data)).await.is_err() {
+                    warn!("Failed to send to recipient");
+                    let _ = cancel_tx.send(RecipientSignal::Gone).await;
+                    break;
+                }
+            }
+            msg = data_rx.recv(), if pending.is_none() => {
+                if !got_data && matches!(msg, Some(RelayMessage::Data(_))) {
+                    let _ = ws_tx.send(Message::Text(phase_frame("active").into())).await;
+                }
+                got_data = true;
                 match msg {
                     Some(RelayMessage::Data(data)) => {
+                        if let Some(egress) = &state.egress {
+                            let egress = egress.clone();
+                            let len = data.len();
+                            pending = Some((data, Box::pin(async move { egress.acquire(len).await })));
+                            continue;
+                        }
                         if ws_tx.send(Message::Binary(data)).await.is_err() {
-                            warn!(transfer_id = %id, "Failed to send to recipient");
-                            let _ = cancel_tx.send(()).await;
+                            warn!("Failed to send to recipient");
+                            let _ = cancel_tx.send(RecipientSignal::Gone).await;
                             break;
                         }
                     }
-                    Some(RelayMessage::Finished) => {
+                    Some(RelayMessage::Restarted) => {
                         let _ = ws_tx.send(Message::Text(
+                            r#"{"type":"restarted"}"#.to_string().into()
+                        )).await;
+                    }
+                    Some(RelayMessage::Finished) => {
+                        let sent = ws_tx.send(Message::Text(
                             r#"{"type":"done"}"#.to_string().into()
                         )).await;
-                        info!(transfer_id = %id, "Transfer delivered to recipient");
+                        if sent.is_ok() {
+                            let _ = delivered_tx.send(());
+                            info!("Transfer delivered to recipient");
+                        } else {
+                            warn!("Recipient disconnected before completion");
+                        }
                         break;
                     }
-                    Some(RelayMessage::Error(e)) => {
-                        let _ = ws_tx.send(Message::Text(
-                            serde_json::to_string(&serde_json::json!({
-                                "type": "error",
-                                "error": e,
-                            })).unwrap().into()
-                        )).await;
+                    Some(RelayMessage::Cancelled(reason)) => {
+                        let _ = ws_tx.send(Message::Text(reason.to_frame().into())).await;
                         break;
                     }
                     None => {
                         let _ = ws_tx.send(Message::Text(
-                            r#"{"type":"error","error":"Sender disconnected"}"#.to_string().into()
+                            CancelReason::SenderDisconnected.to_frame().into()
                         )).await;
                         break;
                     }
@@ -426,16 +869,39 @@ pub async fn handle_receiver(socket: WebSocket, id: String, state: AppState, res
             msg = ws_rx.next() => {
                 match msg {
                     Some(Ok(Message::Close(_))) | None => {
-                        info!(transfer_id = %id, "Recipient disconnected");
-                        let _ = cancel_tx.send(()).await;
+                        info!("Recipient disconnected");
+                        let _ = cancel_tx.send(RecipientSignal::Gone).await;
                         break;
                     }
+                    Some(Ok(Message::Text(text))) if control_type(&text).as_deref() == Some("restart") => {
+                        // Best effort: if a signal is already pending the sender is
+                        // busy with it, and a repeat ask has nothing to add.
+                        let _ = cancel_tx.try_send(RecipientSignal::Restart);
+                    }
                     _ => continue,
                 }
             }
         }
     }
 
+    // Whatever is still queued never reaches this recipient. Closing first
+    // makes the sender's pending reserve fail instead of refilling the queue.
+    data_rx.close();
+    let (mut dropped_frames, mut dropped_bytes) = match pending {
+        Some((data, _)) => (1, data.len() as u64),
+        None => (0u64, 0u64),
+    };
+    while let Ok(msg) = data_rx.try_recv() {
+        if let RelayMessage::Data(data) = msg {
+            dropped_frames += 1;
+            dropped_bytes += data.len() as u64;
+        }
+    }
+    if dropped_frames > 0 {
+        info!(dropped_frames, dropped_bytes, "Discarded undelivered relay frames");
+        state.metrics.record_dropped(dropped_frames, dropped_bytes);
+    }
+
     // Don't mark as Done here — the sender handler decides
     // (it may transition to Reconnecting instead)
 }
@@ -0,0 +1,192 @@
+//! Test harness: runs the real `filetransfer` binary on an ephemeral port and
+//! talks to it over WebSockets, the same way the browser pages do.
+
+#![allow(dead_code)]
+
+use futures_util::{SinkExt, StreamExt};
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+pub type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Per-chunk AES-GCM overhead the sender page adds: 12-byte IV + 16-byte tag.
+pub const FRAME_OVERHEAD: usize = 28;
+
+pub struct Server {
+    child: Child,
+    pub port: u16,
+}
+
+impl Server {
+    pub fn start(env: &[(&str, &str)]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_filetransfer"))
+            .env("PORT", "0")
+            .env("FILET_JSON_STARTUP", "true")
+            .env("RUST_LOG", "off")
+            .envs(env.iter().copied())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("spawn filetransfer");
+
+        // Logs go to stderr, so the startup JSON is the first line on stdout
+        let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+        let startup = lines
+            .next()
+            .expect("startup line")
+            .expect("readable stdout");
+        let startup: serde_json::Value =
+            serde_json::from_str(&startup).expect("startup line is JSON");
+        let port = startup["port"].as_u64().expect("startup line has a port") as u16;
+        // Keep the pipe drained so later output never blocks or fails the server
+        std::thread::spawn(move || lines.for_each(drop));
+
+        Self { child, port }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("ws://127.0.0.1:{}{}", self.port, path)
+    }
+
+    pub async fn connect(&self, path: &str) -> Ws {
+        let (ws, _) = tokio_tungstenite::connect_async(self.url(path))
+            .await
+            .expect("websocket connect");
+        ws
+    }
+
+    /// Upgrade with extra request headers; the HTTP status on rejection.
+    pub async fn try_connect_with(&self, path: &str, headers: &[(&str, &str)]) -> Result<Ws, u16> {
+        let mut request = self.url(path).into_client_request().unwrap();
+        for &(name, value) in headers {
+            request.headers_mut().insert(
+                tungstenite::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        match tokio_tungstenite::connect_async(request).await {
+            Ok((ws, _)) => Ok(ws),
+            Err(tungstenite::Error::Http(response)) => Err(response.status().as_u16()),
+            Err(e) => panic!("websocket connect: {e}"),
+        }
+    }
+
+    /// Plain HTTP GET: the status code and the raw response (headers included).
+    pub async fn get(&self, path: &str) -> (u16, String) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).await.unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let status = response
+            .split(' ')
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .expect("HTTP status line");
+        (status, response)
+    }
+
+    /// Value of an unlabelled counter or gauge from `/metrics`.
+    pub async fn metric(&self, name: &str) -> f64 {
+        let (_, response) = self.get("/metrics").await;
+        response
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("{name} missing from /metrics"))
+            .parse()
+            .unwrap()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+pub async fn send_json(ws: &mut Ws, value: serde_json::Value) {
+    ws.send(Message::Text(value.to_string().into())).await.unwrap();
+}
+
+/// An encrypted-looking frame carrying `plain` bytes of payload.
+pub fn frame(plain: usize, fill: u8) -> Message {
+    Message::Binary(vec![fill; plain + FRAME_OVERHEAD].into())
+}
+
+/// Next text frame as JSON, skipping pings.
+pub async fn next_json(ws: &mut Ws) -> serde_json::Value {
+    loop {
+        let msg = tokio::time::timeout(TIMEOUT, ws.next())
+            .await
+            .expect("timed out waiting for a text frame")
+            .expect("socket closed")
+            .expect("socket error");
+        match msg {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            Message::Ping(_) | Message::Pong(_) => continue,
+            other => panic!("expected text frame, got {other:?}"),
+        }
+    }
+}
+
+/// Next binary frame, skipping pings.
+pub async fn next_binary(ws: &mut Ws) -> Vec<u8> {
+    loop {
+        let msg = tokio::time::timeout(TIMEOUT, ws.next())
+            .await
+            .expect("timed out waiting for a binary frame")
+            .expect("socket closed")
+            .expect("socket error");
+        match msg {
+            Message::Binary(data) => return data.to_vec(),
+            Message::Ping(_) | Message::Pong(_) => continue,
+            other => panic!("expected binary frame, got {other:?}"),
+        }
+    }
+}
+
+/// Next frame must be a `phase` frame announcing `phase`.
+pub async fn expect_phase(ws: &mut Ws, phase: &str) {
+    let msg = next_json(ws).await;
+    assert_eq!(msg["type"], "phase");
+    assert_eq!(msg["phase"], phase);
+}
+
+/// Recipient handshake: `metadata`, then `waiting` until the sender streams.
+pub async fn expect_metadata(ws: &mut Ws) -> serde_json::Value {
+    let metadata = next_json(ws).await;
+    assert_eq!(metadata["type"], "metadata");
+    expect_phase(ws, "waiting").await;
+    metadata
+}
+
+/// Register a transfer and return the sender socket and transfer id.
+pub async fn create_transfer(server: &Server, size: u64) -> (Ws, String) {
+    let mut sender = server.connect("/ws/send").await;
+    send_json(
+        &mut sender,
+        serde_json::json!({ "filename": "test.bin", "size": size }),
+    )
+    .await;
+    let ready = next_json(&mut sender).await;
+    assert_eq!(ready["type"], "ready");
+    let id = ready["id"].as_str().unwrap().to_string();
+    (sender, id)
+}
+
+/// Claimed transfer with both sides past the handshake: sender, recipient, id.
+pub async fn started(server: &Server, size: u64) -> (Ws, Ws, String) {
+    let (mut sender, id) = create_transfer(server, size).await;
+    let mut recipient = server.connect(&format!("/ws/recv/{id}")).await;
+    expect_metadata(&mut recipient).await;
+    assert_eq!(next_json(&mut sender).await["type"], "start");
+    (sender, recipient, id)
+}
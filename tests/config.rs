@@ -0,0 +1,23 @@
+//! Environment parsing: switches accept the usual spellings of on and off.
+
+mod common;
+
+use common::*;
+
+#[tokio::test]
+async fn zero_switches_the_metadata_api_off() {
+    for off in ["0", "off", "no", "FALSE"] {
+        let server = Server::start(&[("FILET_METADATA_API", off)]);
+        let (_sender, id) = create_transfer(&server, 1).await;
+        assert_eq!(server.get(&format!("/api/transfer/{id}")).await.0, 404, "{off}");
+    }
+}
+
+#[tokio::test]
+async fn on_spellings_and_blank_keep_the_metadata_api_on() {
+    for on in ["", "1", "on", "yes"] {
+        let server = Server::start(&[("FILET_METADATA_API", on)]);
+        let (_sender, id) = create_transfer(&server, 1).await;
+        assert_eq!(server.get(&format!("/api/transfer/{id}")).await.0, 200, "{on}");
+    }
+}
@@ -0,0 +1,45 @@
+//! Transfer ids: `FILET_TOKEN_SEED` makes them reproducible across runs.
+
+mod common;
+
+use common::*;
+
+/// Ids handed out by a fresh server for the first `n` transfers.
+async fn first_ids(env: &[(&str, &str)], n: usize) -> Vec<String> {
+    let server = Server::start(env);
+    let mut ids = Vec::new();
+    for _ in 0..n {
+        let (_sender, id) = create_transfer(&server, 1).await;
+        ids.push(id);
+    }
+    ids
+}
+
+fn is_safe_id(id: &str) -> bool {
+    id.len() == 12
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+#[tokio::test]
+async fn same_seed_gives_the_same_ids() {
+    let a = first_ids(&[("FILET_TOKEN_SEED", "1")], 3).await;
+    let b = first_ids(&[("FILET_TOKEN_SEED", "1")], 3).await;
+    assert_eq!(a, b);
+    assert!(a.iter().all(|id| is_safe_id(id)), "{a:?}");
+    // Consecutive ids are unrelated, not shifted copies of each other.
+    assert_ne!(a[0], a[1]);
+    assert_ne!(a[0][1..], a[1][..11]);
+}
+
+#[tokio::test]
+async fn different_seeds_and_unseeded_runs_differ() {
+    let one = first_ids(&[("FILET_TOKEN_SEED", "1")], 2).await;
+    let two = first_ids(&[("FILET_TOKEN_SEED", "2")], 2).await;
+    assert_ne!(one, two);
+
+    let unseeded = first_ids(&[], 2).await;
+    assert!(unseeded.iter().all(|id| is_safe_id(id)), "{unseeded:?}");
+    assert_ne!(unseeded, first_ids(&[], 2).await);
+}
@@ -0,0 +1,96 @@
+//! Per-IP connection limits, the unknown-id tarpit, the shared egress cap,
+//! and how the client address is chosen.
+
+mod common;
+
+use common::*;
+use futures_util::SinkExt;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn forwarded_clients_are_limited_individually() {
+    let server = Server::start(&[
+        ("FILET_MAX_CONNS_PER_IP", "1"),
+        ("FILET_TRUST_FORWARDED_FOR", "true"),
+    ]);
+    let a = [("x-forwarded-for", "198.51.100.7, 203.0.113.1")];
+    let b = [("x-forwarded-for", "203.0.113.2")];
+
+    // The proxy connects from loopback, but each client gets its own slot...
+    let _held = server.try_connect_with("/ws/send", &a).await.unwrap();
+    server.try_connect_with("/ws/send", &b).await.unwrap();
+
+    // ...keyed on the hop the proxy appended, not the spoofable first entry.
+    let spoofed = [("x-forwarded-for", "203.0.113.9, 203.0.113.1")];
+    assert_eq!(server.try_connect_with("/ws/send", &spoofed).await.err(), Some(429));
+}
+
+#[tokio::test]
+async fn forwarded_for_is_ignored_unless_trusted() {
+    let server = Server::start(&[("FILET_MAX_CONNS_PER_IP", "1")]);
+    let forwarded = [("x-forwarded-for", "203.0.113.1")];
+
+    // Without the opt-in the peer (loopback, exempt) is used.
+    let _held = server.try_connect_with("/ws/send", &forwarded).await.unwrap();
+    server.try_connect_with("/ws/send", &forwarded).await.unwrap();
+}
+
+#[tokio::test]
+async fn probing_ids_over_the_socket_is_tarpitted() {
+    let server = Server::start(&[
+        ("FILET_TARPIT_THRESHOLD", "1"),
+        ("FILET_TRUST_FORWARDED_FOR", "true"),
+    ]);
+    let scanner = [("x-forwarded-for", "203.0.113.5")];
+
+    // The first miss is free; each one after that waits another step.
+    let mut delays = Vec::new();
+    for _ in 0..3 {
+        let started = Instant::now();
+        let mut ws = server.try_connect_with("/ws/recv/nosuchid0000", &scanner).await.unwrap();
+        delays.push(started.elapsed());
+        assert_eq!(next_json(&mut ws).await["type"], "error");
+    }
+    assert!(delays[0] < Duration::from_millis(200), "{delays:?}");
+    assert!(delays[2] >= Duration::from_millis(450), "{delays:?}");
+}
+
+#[tokio::test]
+async fn the_egress_cap_bounds_relay_throughput() {
+    const CHUNK: usize = 1000;
+    const FRAMES: usize = 20;
+    let server = Server::start(&[("FILET_TOTAL_EGRESS", "10000")]);
+    let (mut sender, mut recipient, _) = started(&server, (FRAMES * CHUNK) as u64).await;
+
+    let started = Instant::now();
+    for fill in 0..FRAMES {
+        sender.send(frame(CHUNK, fill as u8)).await.unwrap();
+    }
+    send_json(&mut sender, serde_json::json!({ "type": "done" })).await;
+    expect_phase(&mut recipient, "active").await;
+    for _ in 0..FRAMES {
+        next_binary(&mut recipient).await;
+    }
+    assert_eq!(next_json(&mut recipient).await["type"], "done");
+
+    // One second's worth goes out at once; the rest at 10 kB/s.
+    let bytes = (FRAMES * (CHUNK + FRAME_OVERHEAD)) as f64;
+    let floor = Duration::from_secs_f64((bytes - 10_000.0) / 10_000.0);
+    assert!(started.elapsed() >= floor, "{:?} < {floor:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn a_recipient_waiting_on_the_egress_cap_still_notices_its_own_close() {
+    // A single frame takes ten seconds' worth of budget.
+    let server = Server::start(&[("FILET_TOTAL_EGRESS", "100")]);
+    let (mut sender, mut recipient, _) = started(&server, 2000).await;
+
+    sender.send(frame(1000, 1)).await.unwrap();
+    expect_phase(&mut recipient, "active").await;
+    recipient.close(None).await.unwrap();
+    drop(recipient);
+
+    let started = Instant::now();
+    assert_eq!(next_json(&mut sender).await["type"], "paused");
+    assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+}
@@ -0,0 +1,111 @@
+//! Reconnection state machine: WaitingForRecipient -> Active -> Reconnecting
+//! -> resume, the reconnect timeout that ends in `cancelled`, and recipients
+//! that vanish mid-stream.
+
+mod common;
+
+use common::*;
+use futures_util::SinkExt;
+use std::time::Duration;
+
+const CHUNK: usize = 100;
+
+#[tokio::test]
+async fn recipient_reconnects_and_transfer_resumes_from_offset() {
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, id) = started(&server, 3 * CHUNK as u64).await;
+
+    // First chunk arrives, then the recipient drops.
+    sender.send(frame(CHUNK, 1)).await.unwrap();
+    expect_phase(&mut recipient, "active").await;
+    let first = next_binary(&mut recipient).await;
+    assert_eq!(first.len(), CHUNK + FRAME_OVERHEAD);
+    recipient.close(None).await.unwrap();
+    drop(recipient);
+
+    assert_eq!(next_json(&mut sender).await["type"], "paused");
+
+    // Recipient comes back with what it already has.
+    let mut recipient = server
+        .connect(&format!("/ws/recv/{id}?offset={CHUNK}"))
+        .await;
+    expect_metadata(&mut recipient).await;
+
+    let resume = next_json(&mut sender).await;
+    assert_eq!(resume["type"], "resume");
+    assert_eq!(resume["offset"], CHUNK as u64);
+
+    sender.send(frame(CHUNK, 2)).await.unwrap();
+    sender.send(frame(CHUNK, 3)).await.unwrap();
+    send_json(&mut sender, serde_json::json!({ "type": "done" })).await;
+
+    let mut received = first.len();
+    expect_phase(&mut recipient, "active").await;
+    for fill in [2u8, 3] {
+        let data = next_binary(&mut recipient).await;
+        assert!(data.iter().all(|&b| b == fill));
+        received += data.len();
+    }
+    assert_eq!(received, 3 * (CHUNK + FRAME_OVERHEAD));
+    assert_eq!(next_json(&mut recipient).await["type"], "done");
+    assert_eq!(next_json(&mut sender).await["type"], "delivered");
+}
+
+#[tokio::test]
+async fn reconnect_timeout_cancels_the_sender() {
+    let server = Server::start(&[("FILET_RECONNECT_TIMEOUT", "1")]);
+    let (mut sender, mut recipient, id) = started(&server, 2 * CHUNK as u64).await;
+
+    sender.send(frame(CHUNK, 1)).await.unwrap();
+    expect_phase(&mut recipient, "active").await;
+    next_binary(&mut recipient).await;
+    recipient.close(None).await.unwrap();
+    drop(recipient);
+
+    assert_eq!(next_json(&mut sender).await["type"], "paused");
+
+    let cancelled = next_json(&mut sender).await;
+    assert_eq!(cancelled["type"], "cancelled");
+    assert_eq!(cancelled["reason"], "reconnect_timeout");
+
+    // The transfer is gone, so a late reconnect is refused.
+    let mut late = server
+        .connect(&format!("/ws/recv/{id}?offset={CHUNK}"))
+        .await;
+    assert_eq!(next_json(&mut late).await["type"], "error");
+}
+
+#[tokio::test]
+async fn recipient_dying_mid_stream_pauses_the_sender() {
+    // Throttle delivery to a few frames a second so the relay channel is
+    // still holding frames when the recipient vanishes.
+    let server = Server::start(&[("FILET_TOTAL_EGRESS", "1000")]);
+    let (mut sender, mut recipient, id) = started(&server, 64 * CHUNK as u64).await;
+
+    // Keep streaming while the recipient reads once and then vanishes
+    // without a close handshake.
+    for fill in 0..16u8 {
+        sender.send(frame(CHUNK, fill)).await.unwrap();
+    }
+    expect_phase(&mut recipient, "active").await;
+    next_binary(&mut recipient).await;
+    // Give the throttled relay time to fall behind before vanishing.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    drop(recipient);
+    for fill in 16..32u8 {
+        sender.send(frame(CHUNK, fill)).await.unwrap();
+    }
+
+    assert_eq!(next_json(&mut sender).await["type"], "paused");
+    // Frames queued for the dead recipient were discarded, not delivered.
+    assert!(server.metric("filet_relay_dropped_frames_total").await > 0.0);
+
+    // The slot is reclaimable like after a clean disconnect.
+    let mut recipient = server
+        .connect(&format!("/ws/recv/{id}?offset={CHUNK}"))
+        .await;
+    expect_metadata(&mut recipient).await;
+    let resume = next_json(&mut sender).await;
+    assert_eq!(resume["type"], "resume");
+    assert_eq!(resume["offset"], CHUNK as u64);
+}
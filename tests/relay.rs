@@ -0,0 +1,203 @@
+//! Relay-phase guards: filename validation, first-data timeout, lifetime cap,
+//! sender frame validation and backpressure.
+
+mod common;
+
+use common::*;
+use futures_util::SinkExt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+async fn assert_rejected(sender: &mut Ws, recipient: &mut Ws) {
+    assert_eq!(next_json(sender).await["type"], "error");
+    let cancelled = next_json(recipient).await;
+    assert_eq!(cancelled["type"], "cancelled");
+    assert_eq!(cancelled["reason"], "sender_protocol_error");
+}
+
+#[tokio::test]
+async fn recipient_gives_up_on_a_sender_that_never_streams() {
+    let server = Server::start(&[("FILET_FIRST_DATA_TIMEOUT", "1")]);
+    let (mut sender, mut recipient, _) = started(&server, 100).await;
+
+    // The sender is told to start but sends nothing.
+    let cancelled = next_json(&mut recipient).await;
+    assert_eq!(cancelled["type"], "cancelled");
+    assert_eq!(cancelled["reason"], "sender_timeout");
+
+    // The sender is told the same, and the transfer is over rather than paused.
+    let cancelled = next_json(&mut sender).await;
+    assert_eq!(cancelled["type"], "cancelled");
+    assert_eq!(cancelled["reason"], "sender_timeout");
+}
+
+#[tokio::test]
+async fn the_lifetime_cap_ends_the_transfer_for_both_sides() {
+    let server = Server::start(&[("FILET_MAX_TRANSFER_DURATION", "1")]);
+    let (mut sender, mut recipient, id) = started(&server, 200).await;
+
+    sender.send(frame(100, 1)).await.unwrap();
+    expect_phase(&mut recipient, "active").await;
+    next_binary(&mut recipient).await;
+
+    for ws in [&mut recipient, &mut sender] {
+        let cancelled = next_json(ws).await;
+        assert_eq!(cancelled["type"], "cancelled");
+        assert_eq!(cancelled["reason"], "max_duration_exceeded");
+    }
+
+    // Reconnecting doesn't buy the recipient a fresh lifetime.
+    let mut again = server.connect(&format!("/ws/recv/{id}?offset=100")).await;
+    assert_eq!(next_json(&mut again).await["type"], "error");
+}
+
+#[tokio::test]
+async fn data_beyond_the_announced_size_is_rejected() {
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, _) = started(&server, 150).await;
+
+    sender.send(frame(100, 1)).await.unwrap();
+    expect_phase(&mut recipient, "active").await;
+    assert_eq!(next_binary(&mut recipient).await.len(), 100 + FRAME_OVERHEAD);
+
+    sender.send(frame(100, 2)).await.unwrap();
+    assert_rejected(&mut sender, &mut recipient).await;
+}
+
+#[tokio::test]
+async fn done_before_the_announced_size_is_rejected() {
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, _) = started(&server, 150).await;
+
+    sender.send(frame(100, 1)).await.unwrap();
+    expect_phase(&mut recipient, "active").await;
+    assert_eq!(next_binary(&mut recipient).await.len(), 100 + FRAME_OVERHEAD);
+
+    send_json(&mut sender, serde_json::json!({ "type": "done" })).await;
+    assert_rejected(&mut sender, &mut recipient).await;
+}
+
+#[tokio::test]
+async fn frames_shorter_than_the_chunk_overhead_are_rejected() {
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, _) = started(&server, 100).await;
+
+    sender
+        .send(Message::Binary(vec![0; FRAME_OVERHEAD - 1].into()))
+        .await
+        .unwrap();
+    assert_rejected(&mut sender, &mut recipient).await;
+}
+
+#[tokio::test]
+async fn spurious_text_frames_are_limited() {
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, _) = started(&server, 100).await;
+
+    // A few unknown control messages are tolerated...
+    for _ in 0..3 {
+        send_json(&mut sender, serde_json::json!({ "type": "hello" })).await;
+    }
+    sender.send(frame(100, 1)).await.unwrap();
+    expect_phase(&mut recipient, "active").await;
+    assert_eq!(next_binary(&mut recipient).await.len(), 100 + FRAME_OVERHEAD);
+
+    // ...but not more than the server's limit of 8; the ninth ends the relay.
+    for _ in 0..6 {
+        send_json(&mut sender, serde_json::json!({ "type": "hello" })).await;
+    }
+    assert_rejected(&mut sender, &mut recipient).await;
+}
+
+#[tokio::test]
+async fn oversized_text_frames_are_rejected() {
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, _) = started(&server, 100).await;
+
+    sender
+        .send(Message::Text("x".repeat(4096).into()))
+        .await
+        .unwrap();
+    assert_rejected(&mut sender, &mut recipient).await;
+}
+
+#[tokio::test]
+async fn unsafe_filenames_are_refused_at_registration() {
+    let server = Server::start(&[]);
+    let too_long = "a".repeat(256);
+    for (name, error) in [
+        ("../../etc/passwd", "Invalid filename: name contains a path separator"),
+        ("..\\boot.ini", "Invalid filename: name contains a path separator"),
+        ("report\nfinal.pdf", "Invalid filename: name contains control characters"),
+        ("", "Invalid filename: name is empty"),
+        ("   ", "Invalid filename: name is empty"),
+        ("..", "Invalid filename: name is empty"),
+        (too_long.as_str(), "Invalid filename: name is too long"),
+    ] {
+        let mut sender = server.connect("/ws/send").await;
+        send_json(&mut sender, serde_json::json!({ "filename": name, "size": 1 })).await;
+        let reply = next_json(&mut sender).await;
+        assert_eq!(reply["type"], "error", "{name:?}");
+        assert_eq!(reply["error"], error, "{name:?}");
+    }
+}
+
+#[tokio::test]
+async fn filenames_are_trimmed_and_may_be_exactly_the_limit() {
+    let server = Server::start(&[]);
+    let longest = "b".repeat(255);
+    for (name, stored) in [("  notes.txt  ", "notes.txt"), (longest.as_str(), longest.as_str())] {
+        let mut sender = server.connect("/ws/send").await;
+        send_json(&mut sender, serde_json::json!({ "filename": name, "size": 1 })).await;
+        let ready = next_json(&mut sender).await;
+        assert_eq!(ready["type"], "ready");
+
+        let id = ready["id"].as_str().unwrap();
+        let mut recipient = server.connect(&format!("/ws/recv/{id}")).await;
+        assert_eq!(expect_metadata(&mut recipient).await["filename"], stored);
+    }
+}
+
+#[tokio::test]
+async fn a_recipient_that_stops_reading_stalls_the_sender() {
+    const CHUNK: usize = 64 * 1024;
+    // Comfortably more than the relay channel plus both sockets' kernel buffers
+    const FRAMES: usize = 1024;
+
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, _) = started(&server, (FRAMES * CHUNK) as u64).await;
+
+    let sent = Arc::new(AtomicUsize::new(0));
+    let streaming = tokio::spawn({
+        let sent = sent.clone();
+        async move {
+            for fill in 0..FRAMES {
+                sender.send(frame(CHUNK, fill as u8)).await.unwrap();
+                sent.fetch_add(1, Ordering::Relaxed);
+            }
+            send_json(&mut sender, serde_json::json!({ "type": "done" })).await;
+            sender
+        }
+    });
+
+    // The recipient reads nothing; once the relay has waited out the warning
+    // threshold the sender is blocked too.
+    tokio::time::sleep(Duration::from_secs(6)).await;
+    assert_eq!(server.metric("filet_relay_stalls_total").await, 1.0);
+    let blocked_at = sent.load(Ordering::Relaxed);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(sent.load(Ordering::Relaxed), blocked_at);
+    assert!(blocked_at < FRAMES, "sender never blocked");
+
+    // Reading again drains the channel and lets the sender finish.
+    expect_phase(&mut recipient, "active").await;
+    for _ in 0..FRAMES {
+        assert_eq!(next_binary(&mut recipient).await.len(), CHUNK + FRAME_OVERHEAD);
+    }
+    let mut sender = tokio::time::timeout(TIMEOUT, streaming).await.unwrap().unwrap();
+    assert_eq!(next_json(&mut recipient).await["type"], "done");
+    assert_eq!(next_json(&mut sender).await["type"], "delivered");
+    assert!(server.metric("filet_relay_stalled_seconds_total").await >= 5.0);
+}
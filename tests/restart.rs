@@ -0,0 +1,142 @@
+//! Recipient-requested restarts: the sender replays from offset 0, frames
+//! from the abandoned stream are dropped, the restart count is capped, and a
+//! restart that crosses `done` or a reconnect doesn't wedge the transfer.
+
+mod common;
+
+use common::*;
+use futures_util::SinkExt;
+use std::time::Duration;
+
+const CHUNK: usize = 100;
+
+#[tokio::test]
+async fn restart_replays_the_file_from_the_start() {
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, _) = started(&server, 2 * CHUNK as u64).await;
+
+    sender.send(frame(CHUNK, 1)).await.unwrap();
+    expect_phase(&mut recipient, "active").await;
+    next_binary(&mut recipient).await;
+
+    send_json(&mut recipient, serde_json::json!({ "type": "restart" })).await;
+    assert_eq!(next_json(&mut sender).await["type"], "restart");
+
+    // Still in flight from the old stream when the sender saw `restart`.
+    sender.send(frame(CHUNK, 9)).await.unwrap();
+    send_json(&mut sender, serde_json::json!({ "type": "restarted" })).await;
+    assert_eq!(next_json(&mut recipient).await["type"], "restarted");
+
+    // The full size is available again after the cut.
+    for fill in [2u8, 3] {
+        sender.send(frame(CHUNK, fill)).await.unwrap();
+    }
+    send_json(&mut sender, serde_json::json!({ "type": "done" })).await;
+    for fill in [2u8, 3] {
+        let data = next_binary(&mut recipient).await;
+        assert!(data.iter().all(|&b| b == fill));
+    }
+    assert_eq!(next_json(&mut recipient).await["type"], "done");
+    assert_eq!(next_json(&mut sender).await["type"], "delivered");
+}
+
+#[tokio::test]
+async fn restarts_are_capped() {
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, _) = started(&server, CHUNK as u64).await;
+
+    for _ in 0..3 {
+        send_json(&mut recipient, serde_json::json!({ "type": "restart" })).await;
+        assert_eq!(next_json(&mut sender).await["type"], "restart");
+        send_json(&mut sender, serde_json::json!({ "type": "restarted" })).await;
+        assert_eq!(next_json(&mut recipient).await["type"], "restarted");
+    }
+
+    send_json(&mut recipient, serde_json::json!({ "type": "restart" })).await;
+    for ws in [&mut sender, &mut recipient] {
+        let cancelled = next_json(ws).await;
+        assert_eq!(cancelled["type"], "cancelled");
+        assert_eq!(cancelled["reason"], "too_many_restarts");
+    }
+}
+
+#[tokio::test]
+async fn a_done_that_crosses_the_restart_cancels_the_sender() {
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, _) = started(&server, 2 * CHUNK as u64).await;
+
+    sender.send(frame(CHUNK, 1)).await.unwrap();
+    expect_phase(&mut recipient, "active").await;
+    next_binary(&mut recipient).await;
+
+    // The sender sent its last frame and `done` before the restart request
+    // reached it, so it never answers.
+    send_json(&mut recipient, serde_json::json!({ "type": "restart" })).await;
+    assert_eq!(next_json(&mut sender).await["type"], "restart");
+    send_json(&mut sender, serde_json::json!({ "type": "done" })).await;
+
+    let cancelled = next_json(&mut sender).await;
+    assert_eq!(cancelled["type"], "cancelled");
+    assert_eq!(cancelled["reason"], "restart_too_late");
+    // The recipient isn't left waiting for a `restarted` either.
+    assert_eq!(next_json(&mut recipient).await["type"], "done");
+}
+
+#[tokio::test]
+async fn a_reconnect_abandons_a_pending_restart() {
+    let server = Server::start(&[]);
+    let (mut sender, mut recipient, id) = started(&server, 2 * CHUNK as u64).await;
+
+    sender.send(frame(CHUNK, 1)).await.unwrap();
+    expect_phase(&mut recipient, "active").await;
+    next_binary(&mut recipient).await;
+
+    // The recipient asks for a restart and drops before the sender answers.
+    send_json(&mut recipient, serde_json::json!({ "type": "restart" })).await;
+    assert_eq!(next_json(&mut sender).await["type"], "restart");
+    recipient.close(None).await.unwrap();
+    assert_eq!(next_json(&mut sender).await["type"], "paused");
+
+    // Back with what it had; the new connection resumes instead of waiting
+    // for a `restarted` that was never coming.
+    let mut recipient = server
+        .connect(&format!("/ws/recv/{id}?offset={CHUNK}"))
+        .await;
+    expect_metadata(&mut recipient).await;
+    let resume = next_json(&mut sender).await;
+    assert_eq!(resume["type"], "resume");
+    assert_eq!(resume["offset"], CHUNK as u64);
+
+    sender.send(frame(CHUNK, 2)).await.unwrap();
+    send_json(&mut sender, serde_json::json!({ "type": "done" })).await;
+    expect_phase(&mut recipient, "active").await;
+    assert!(next_binary(&mut recipient).await.iter().all(|&b| b == 2));
+    assert_eq!(next_json(&mut recipient).await["type"], "done");
+    assert_eq!(next_json(&mut sender).await["type"], "delivered");
+}
+
+#[tokio::test]
+async fn a_restart_after_done_does_not_wedge_a_closing_recipient() {
+    // Throttled so frames are still queued when the late requests arrive, but
+    // few enough to fit the relay channel: the relay itself is already done.
+    let server = Server::start(&[("FILET_TOTAL_EGRESS", "1000")]);
+    let (mut sender, mut recipient, _) = started(&server, 15 * CHUNK as u64).await;
+
+    for fill in 0..15u8 {
+        sender.send(frame(CHUNK, fill)).await.unwrap();
+    }
+    send_json(&mut sender, serde_json::json!({ "type": "done" })).await;
+    expect_phase(&mut recipient, "active").await;
+    next_binary(&mut recipient).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The sender has already finished, so nothing answers this restart.
+    send_json(&mut recipient, serde_json::json!({ "type": "restart" })).await;
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    recipient.close(None).await.unwrap();
+    drop(recipient);
+
+    let cancelled = next_json(&mut sender).await;
+    assert_eq!(cancelled["type"], "cancelled");
+    assert_eq!(cancelled["reason"], "recipient_disconnected");
+}